@@ -1,4 +1,6 @@
-use std::usize;
+use std::sync::Arc;
+
+use parser::ast::Annotation;
 
 pub trait DocumentationType {
     fn get_root_path() -> String;
@@ -26,3 +28,16 @@ pub trait DocumentationType {
         }
     }
 }
+
+/// Joins Markdown lines back into a single block of text
+pub fn join_lines(lines: &[Arc<str>], separator: &str) -> String {
+    lines.join(separator)
+}
+
+/// Markdown for each condition of `@if` annotations, which a definition is only compiled under
+pub fn conditions(annotations: &[Annotation]) -> Vec<String> {
+    annotations.iter()
+        .filter_map(Annotation::condition)
+        .map(|condition| format!("`{}`", condition))
+        .collect()
+}
@@ -5,6 +5,8 @@ use std::fmt;
 pub enum Error {
     IoError(io::Error),
     FormatError(fmt::Error),
+    TemplateError(askama::Error),
+    JsonError(serde_json::Error),
 }
 
 impl From<io::Error> for Error {
@@ -19,3 +21,14 @@ impl From<fmt::Error> for Error {
     }
 }
 
+impl From<askama::Error> for Error {
+    fn from(err: askama::Error) -> Self {
+        Error::TemplateError(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::JsonError(err)
+    }
+}
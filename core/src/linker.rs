@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+use parser::ast::{FileDefinition, TypeDeclaration, VariableType};
+
+/// Path of the page documenting each definition, by its fully qualified name
+pub type PagePaths = HashMap<String, String>;
+
+/// Links names used within a script file to the pages of the definitions they refer to, which are
+/// found through the file's module and imports
+#[derive(Debug, Clone, Copy)]
+pub struct Linker<'a> {
+    pub file: &'a FileDefinition,
+    pub pages: &'a PagePaths,
+}
+
+impl<'a> Linker<'a> {
+    /// Markdown for a reference to a definition, which is a link if it has a page
+    pub fn link(&self, name: &str) -> String {
+        self.link_text(name, name)
+    }
+
+    /// Markdown for a type, linking to the page of the definition it contains, if any
+    pub fn link_type(&self, type_: &TypeDeclaration) -> String {
+        let mut inner = type_;
+        while let Some(subtype) = &inner.subtype {
+            inner = subtype;
+        }
+        match &inner.type_ {
+            VariableType::Compound(name) => self.link_text(name.as_str(), &type_.to_string()),
+            _ => format!("`{}`", type_),
+        }
+    }
+
+    fn link_text(&self, name: &str, text: &str) -> String {
+        match self.file.resolve(name, |qualified| self.pages.contains_key(qualified)) {
+            Some(qualified) => format!("[`{}`]({})", text, self.pages[&qualified]),
+            None => format!("`{}`", text),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+
+use askama::Template;
+
+use crate::error::Error;
+use crate::linker::PagePaths;
+
+/// JavaScript API of the parser built for wasm, which the lookup page loads it with
+pub const PARSER_JS: &str = include_str!("../../parser/js/redscript_parser.js");
+
+/// Page that parses scripts pasted into it in the browser, and links the types they use to their
+/// pages
+#[derive(Debug, Clone, Template)]
+#[template(path="lookup.md")]
+pub struct Lookup {
+    /// Path of the page of each definition by its fully qualified name, relative to this page, as
+    /// a JSON object
+    pub pages: String,
+}
+
+impl Lookup {
+    pub fn new(pages: &PagePaths) -> Result<Self, Error> {
+        // Sorted so the page is the same for the same scripts
+        let pages: BTreeMap<_, _> = pages
+            .iter()
+            .map(|(name, path)| (name, format!("..{}", path)))
+            .collect();
+        Ok(Lookup { pages: serde_json::to_string(&pages)? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_pages() {
+        let mut pages = PagePaths::new();
+        pages.insert("Game.Player".to_string(), "/class/Game.Player".to_string());
+        pages.insert("Direction".to_string(), "/enum/Direction".to_string());
+        let page = Lookup::new(&pages).unwrap().render().unwrap();
+
+        let json = page
+            .lines()
+            .find_map(|line| line.strip_prefix("const pages = "))
+            .and_then(|json| json.strip_suffix(';'))
+            .unwrap();
+        assert_eq!(json, r#"{"Direction":"../enum/Direction","Game.Player":"../class/Game.Player"}"#);
+        assert!(page.contains(r#"from "../assets/redscript_parser.js""#));
+        assert!(page.contains(r#"fetch("../assets/redscript_parser.wasm")"#));
+    }
+}
@@ -1,29 +1,86 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 use askama::Template;
 use gumdrop::Options;
 use log::LevelFilter;
+use parser::ast::{AnyDefinition, FileDefinition};
+use parser::diagnostic::{Diagnostic, Severity};
+use parser::source::DecodeError;
 use simplelog::{ColorChoice, TermLogger, TerminalMode};
 
 use crate::error::Error;
+use crate::linker::{Linker, PagePaths};
+use crate::lookup::{Lookup, PARSER_JS};
 use crate::doctype::DocumentationType;
-use crate::type_enum::{EnumField, Enum, EnumGroup};
+use crate::sourcefile::SourceFile;
+use crate::type_class::{Class, ClassGroup};
+use crate::type_enum::{Enum, EnumGroup};
 
 pub mod error;
+pub mod linker;
+pub mod lookup;
+pub mod type_class;
 pub mod type_enum;
 pub mod doctype;
+pub mod sourcefile;
 
+/// Generates documentation from the input scripts, unless a command is given
 #[derive(Debug, Options)]
 struct Opts {
-    #[options(required, short = "i", help = "input bundle file (eg. r6\\cache\\final.redscripts)")]
-    input: PathBuf,
-    #[options(required, short = "o", help = "output directory (eg. mkdocs\\docs)")]
-    output: PathBuf,
+    #[options(short = "i", help = "input script file or directory of scripts (eg. decompiled r6\\scripts)")]
+    input: Option<PathBuf>,
+    #[options(short = "o", help = "output directory (eg. mkdocs\\docs)")]
+    output: Option<PathBuf>,
+    #[options(no_short, help = "parser built for wasm32-unknown-unknown, which adds a page for looking up pasted scripts")]
+    wasm: Option<PathBuf>,
+    #[options(command)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Options)]
+enum Command {
+    #[options(help = "rewrite script files in the canonical format")]
+    Format(FormatOpts),
+    #[options(help = "write the definitions parsed from script files")]
+    DumpAst(DumpAstOpts),
+}
+
+#[derive(Debug, Options)]
+struct FormatOpts {
+    #[options(free, required, help = "script files or directories of scripts, which are written as UTF-8")]
+    paths: Vec<PathBuf>,
+    #[options(help = "list the files that aren't formatted, without rewriting them")]
+    check: bool,
+}
+
+#[derive(Debug, Options)]
+struct DumpAstOpts {
+    #[options(free, required, help = "script files or directories of scripts")]
+    paths: Vec<PathBuf>,
+    #[options(help = "write JSON rather than Rust's debug format")]
+    json: bool,
+    #[options(help = "also parse the statements of function bodies")]
+    bodies: bool,
+    #[options(short = "o", help = "output file, rather than stdout")]
+    output: Option<PathBuf>,
 }
 
 fn main() -> Result<(), Error> {
+    // Skips the program name
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let opts = Opts::parse_args_default(&args);
+
+    // Definitions may be dumped to stdout, so logging moves out of their way
+    let terminal_mode = match &opts {
+        Ok(Opts { command: Some(Command::DumpAst(_)), .. }) => TerminalMode::Stderr,
+        _ => TerminalMode::Stdout,
+    };
+
     let log_config = simplelog::ConfigBuilder::new()
         .set_time_format_str("")
         .build();
@@ -31,69 +88,313 @@ fn main() -> Result<(), Error> {
     TermLogger::init(
         LevelFilter::Info,
         log_config,
-        TerminalMode::Stdout,
+        terminal_mode,
         ColorChoice::Auto
     ).unwrap();
 
-    // Skips the program name
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    match Opts::parse_args_default(&args) {
-        Ok(opts) => {
-            run(opts).map_err(|err| {
-                log::error!("{:?}", err);
-                err
-            }
-        )},
+    let result = match opts {
+        Ok(Opts { command: Some(Command::Format(opts)), .. }) => format(opts),
+        Ok(Opts { command: Some(Command::DumpAst(opts)), .. }) => dump_ast(opts),
+        Ok(Opts { input: Some(input), output: Some(output), wasm, .. }) => run(&input, &output, wasm.as_deref()),
+        Ok(_) => {
+            log::error!("missing required options `--input` and `--output`");
+            log_usage();
+            Ok(())
+        }
         Err(err) => {
             log::error!("{}", err);
-            log::info!("Usage:\n{}", Opts::usage());
-            
+            log_usage();
             Ok(())
         }
+    };
+    result.map_err(|err| {
+        log::error!("{:?}", err);
+        err
+    })
+}
+
+fn log_usage() {
+    log::info!("Usage:\n{}\n\nCommands:\n{}", Opts::usage(), Opts::command_list().unwrap_or_default());
+}
+
+/// Recursively collects every script file at the input path, which may also be a single file
+fn find_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            find_scripts(&entry?.path(), scripts)?;
+        }
+    }
+    else if matches!(path.extension().and_then(|ext| ext.to_str()), Some("reds") | Some("script")) {
+        scripts.push(path.to_path_buf());
+    }
+
+    Ok(())
+}
+
+/// Script file parsed into its definitions and diagnostics, or the reason it couldn't be decoded
+type ParsedScript = Result<(FileDefinition, Vec<Diagnostic>), DecodeError>;
+
+/// Reads and parses script files, each given by its path and the name to parse it under, across
+/// every available core, along with their function bodies if `bodies` is set. Results are returned
+/// in the same order as the files.
+fn parse_scripts(scripts: &[(PathBuf, String)], bodies: bool) -> io::Result<Vec<ParsedScript>> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get).min(scripts.len().max(1));
+    // Files vary a lot in size, so each thread takes the next file as soon as it's free
+    let next = AtomicUsize::new(0);
+    let parse_next = || -> io::Result<Vec<(usize, ParsedScript)>> {
+        let mut parsed = Vec::new();
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let (path, filename) = match scripts.get(index) {
+                Some(script) => script,
+                None => return Ok(parsed),
+            };
+            let source = parser::source::decode(&fs::read(path)?);
+            parsed.push((index, source.map(|source| {
+                let (mut file, mut diagnostics) = parser::parse_file_recovering(filename, &source.text);
+                if bodies {
+                    diagnostics.extend(parser::parse_bodies(&mut file, &source.text));
+                }
+                source.map_spans(&mut file, &mut diagnostics);
+                (file, diagnostics)
+            })));
+        }
+    };
+
+    let mut parsed: Vec<(usize, ParsedScript)> = Vec::with_capacity(scripts.len());
+    thread::scope(|scope| -> io::Result<()> {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(parse_next)).collect();
+        for worker in workers {
+            parsed.extend(worker.join().expect("parser thread panicked")?);
+        }
+        Ok(())
+    })?;
+
+    parsed.sort_by_key(|(index, _)| *index);
+    Ok(parsed.into_iter().map(|(_, script)| script).collect())
+}
+
+/// Rewrites script files in the canonical format, or with `--check` lists those that aren't, and
+/// exits with a failure if there are any
+fn format(opts: FormatOpts) -> Result<(), Error> {
+    let mut scripts = Vec::new();
+    for path in &opts.paths {
+        find_scripts(path, &mut scripts)?;
+    }
+    scripts.sort();
+
+    let mut unformatted = 0;
+    for path in &scripts {
+        let bytes = fs::read(path)?;
+        let source = match parser::source::decode(&bytes) {
+            Ok(source) => source.text,
+            Err(err) => {
+                log::warn!("{}: skipped, {}", path.display(), err);
+                continue;
+            }
+        };
+
+        let formatted = parser::print::format(&source);
+        if formatted.as_bytes() == bytes.as_slice() {
+            continue;
+        }
+        unformatted += 1;
+        if opts.check {
+            log::info!("{}: not formatted", path.display());
+        }
+        else {
+            fs::write(path, formatted)?;
+        }
+    }
+
+    if opts.check {
+        log::info!("{} of {} script files aren't formatted", unformatted, scripts.len());
+        if unformatted > 0 {
+            std::process::exit(1);
+        }
+    }
+    else {
+        log::info!("Formatted {} of {} script files", unformatted, scripts.len());
+    }
+    Ok(())
+}
+
+/// Writes the definitions and diagnostics parsed from each script file, without resolving
+/// references between files
+fn dump_ast(opts: DumpAstOpts) -> Result<(), Error> {
+    let mut scripts = Vec::new();
+    for path in &opts.paths {
+        find_scripts(path, &mut scripts)?;
+    }
+    scripts.sort();
+
+    let mut output: Box<dyn Write> = match &opts.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+
+    let scripts: Vec<(PathBuf, String)> = scripts.into_iter()
+        .map(|path| {
+            let filename = path.to_string_lossy().replace('\\', "/");
+            (path, filename)
+        })
+        .collect();
+
+    let mut dumps = Vec::new();
+    for ((_, filename), parsed) in scripts.iter().zip(parse_scripts(&scripts, opts.bodies)?) {
+        let (def, diagnostics) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                log::warn!("{}: skipped, {}", filename, err);
+                continue;
+            }
+        };
+
+        if opts.json {
+            dumps.push(serde_json::json!({
+                "file": serde_json::to_value(&def)?,
+                "diagnostics": serde_json::to_value(&diagnostics)?,
+            }));
+        }
+        else {
+            writeln!(&mut output, "{:#?}\n{:#?}", def, diagnostics)?;
+        }
+    }
+
+    if opts.json {
+        serde_json::to_writer_pretty(&mut output, &dumps)?;
+        writeln!(&mut output)?;
     }
+    output.flush()?;
+    Ok(())
 }
 
-fn run(opts: Opts) -> Result<(), Error> {
-
-    let fields1 = vec![
-        EnumField {name:"STATUS_INVALID", value: "0"},
-        EnumField {name:"STATUS_BOUND", value: "1"},
-        EnumField {name:"STATUS_READY", value: "2"},
-        EnumField {name:"STATUS_PROGRESS", value: "3"},
-        EnumField {name:"STATUS_COMPLETE", value: "4"},
-        EnumField {name:"STATUS_FAILURE", value: "5"},
-    ];
-    let fields2 = vec![
-        EnumField {name:"STATUS_INVALID", value: "0"},
-        EnumField {name:"STATUS_BOUND", value: "1"},
-        EnumField {name:"STATUS_READY", value: "2"},
-        EnumField {name:"STATUS_PROGRESS", value: "3"},
-        EnumField {name:"STATUS_COMPLETE", value: "4"},
-        EnumField {name:"STATUS_FAILURE", value: "5"},
-    ];
-
-    let test_enums = vec![
-        Enum {
-            name: "moveMovementType",
-            fields: &fields1
-        },
-        Enum {
-            name: "gameEActionStatus",
-            fields: &fields2
-        },
-    ];
+fn run(input: &Path, output_dir: &Path, wasm: Option<&Path>) -> Result<(), Error> {
+    let root = if input.is_dir() {
+        input
+    }
+    else {
+        input.parent().unwrap_or_else(|| Path::new(""))
+    };
+
+    let mut scripts = Vec::new();
+    find_scripts(input, &mut scripts)?;
+    scripts.sort();
+    // Files are named by their path relative to the input
+    let scripts: Vec<(PathBuf, String)> = scripts.into_iter()
+        .map(|path| {
+            let filename = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            (path, filename)
+        })
+        .collect();
+
+    // Parsed files, and the full path of each
+    let mut files: Vec<FileDefinition> = Vec::new();
+    let mut fullpaths = Vec::new();
+    let mut skipped = 0;
+    for ((path, filename), parsed) in scripts.iter().zip(parse_scripts(&scripts, false)?) {
+        let (def, diagnostics) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                log::warn!("{}: skipped, {}", filename, err);
+                continue;
+            }
+        };
+
+        let mut file_skipped = 0;
+        for diagnostic in &diagnostics {
+            match diagnostic.severity {
+                Severity::Error => {
+                    log::debug!("{}:{}", filename, diagnostic);
+                    file_skipped += 1;
+                }
+                Severity::Warning => log::warn!("{}:{}", filename, diagnostic),
+            }
+        }
+        if file_skipped > 0 {
+            log::warn!("{}: skipped {} unparseable items", filename, file_skipped);
+            skipped += file_skipped;
+        }
+
+        files.push(def);
+        fullpaths.push(path.to_string_lossy().into_owned());
+    }
+    log::info!("Parsed {} script files, skipping {} items", files.len(), skipped);
+
+    // Detached comments may document definitions in any other file
+    for (index, diagnostic) in parser::doc::attach_detached(&mut files) {
+        log::warn!("{}:{}", files[index].name.as_str(), diagnostic);
+    }
+
+    // Every page is known up front, so references between them can be linked
+    let mut pages = PagePaths::new();
+    for file in &files {
+        for def in &file.defs {
+            let (root, name) = match def {
+                AnyDefinition::Enum(enum_) => (Enum::get_root_path(), &enum_.name),
+                AnyDefinition::Class(class) => (Class::get_root_path(), &class.name),
+                _ => continue,
+            };
+            let name = file.qualify(name.as_str());
+            pages.insert(name.clone(), format!("{}/{}", root, name));
+        }
+    }
+
+    let mut enums = Vec::new();
+    let mut classes = Vec::new();
+    for (file, fullpath) in files.iter().zip(&fullpaths) {
+        let source = SourceFile { filename: file.name.as_str(), fullpath };
+        let linker = Linker { file, pages: &pages };
+        for def in &file.defs {
+            match def {
+                AnyDefinition::Enum(enum_) => enums.push(Enum::new(enum_, source, linker)),
+                AnyDefinition::Class(class) => classes.push(Class::new(class, source, linker)),
+                _ => {}
+            }
+        }
+    }
+    enums.sort_by(|a, b| a.name.cmp(&b.name));
+    classes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let enum_dir = output_dir.join("enum");
+    fs::create_dir_all(&enum_dir)?;
+    for enum_ in &enums {
+        let mut output = BufWriter::new(File::create(enum_dir.join(format!("{}.md", enum_.name)))?);
+        write!(&mut output, "{}", enum_.render()?)?;
+    }
 
     let enum_group = EnumGroup {
         name: "All Enums",
-        enums: &test_enums,
+        enums: &enums,
     };
 
-    println!("Enum::get_type_link() = {}", Enum::get_type_link());
+    let mut output = BufWriter::new(File::create(output_dir.join("enum.md"))?);
+    write!(&mut output, "{}", enum_group.render()?)?;
 
-    let path = opts.output.as_path().join("testenum.md");
-    let mut output = BufWriter::new(File::create(path)?);
+    let class_dir = output_dir.join("class");
+    fs::create_dir_all(&class_dir)?;
+    for class in &classes {
+        let mut output = BufWriter::new(File::create(class_dir.join(format!("{}.md", class.name)))?);
+        write!(&mut output, "{}", class.render()?)?;
+    }
 
-    write!(&mut output, "{}", enum_group.render().unwrap())?;
+    let class_group = ClassGroup {
+        name: "All Classes",
+        classes: &classes,
+    };
+
+    let mut output = BufWriter::new(File::create(output_dir.join("class.md"))?);
+    write!(&mut output, "{}", class_group.render()?)?;
+
+    if let Some(wasm) = wasm {
+        let assets_dir = output_dir.join("assets");
+        fs::create_dir_all(&assets_dir)?;
+        fs::copy(wasm, assets_dir.join("redscript_parser.wasm"))?;
+        fs::write(assets_dir.join("redscript_parser.js"), PARSER_JS)?;
+
+        let mut output = BufWriter::new(File::create(output_dir.join("lookup.md"))?);
+        write!(&mut output, "{}", Lookup::new(&pages)?.render()?)?;
+    }
 
     Ok(())
 }
@@ -1,7 +1,5 @@
-use askama::Template;
-
+#[derive(Debug, Clone, Copy)]
 pub struct SourceFile<'a> {
     pub filename: &'a str,
     pub fullpath: &'a str,
-
 }
@@ -0,0 +1,185 @@
+use askama::Template;
+use parser::ast::{Annotation, ClassDefinition, MemberDefinition};
+
+use crate::doctype::{conditions, join_lines, DocumentationType};
+use crate::linker::Linker;
+use crate::sourcefile::SourceFile;
+
+#[derive(Debug, Clone)]
+pub struct ClassField<'a> {
+    pub name: &'a str,
+    /// Markdown for the type, linking to its page
+    pub type_: String,
+    /// Storage and persistence traits, such as `static` or `persistent`, and any conditions the
+    /// field is compiled under, as inline code badges
+    pub badges: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassMethod<'a> {
+    pub name: &'a str,
+    /// Declaration as redscript, without the body
+    pub signature: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Template)]
+#[template(path="class-full.md")]
+pub struct Class<'a> {
+    /// Name qualified with its module
+    pub name: String,
+    /// Module the class belongs to, or empty
+    pub module: String,
+    /// Markdown for the base class, linking to its page, or empty
+    pub base: String,
+    /// Declaration as redscript, without the members
+    pub signature: String,
+    /// Markdown for each condition the definition is only compiled under
+    pub conditions: Vec<String>,
+    pub description: String,
+    pub sections: Vec<(&'a str, String)>,
+    /// Markdown for each `@see` reference
+    pub see: Vec<String>,
+    pub fields: Vec<ClassField<'a>>,
+    pub methods: Vec<ClassMethod<'a>>,
+    pub source: SourceFile<'a>,
+    pub line: usize,
+}
+
+impl<'a> Class<'a> {
+    pub fn new(def: &'a ClassDefinition, source: SourceFile<'a>, linker: Linker<'a>) -> Self {
+        let doc = def.comments.as_ref().map(|comments| &comments.doc);
+
+        Class {
+            name: linker.file.qualify(def.name.as_str()),
+            module: linker.file.module.as_ref().map_or(String::new(), |module| module.name()),
+            base: def.base.as_ref().map_or(String::new(), |base| linker.link(base.as_str())),
+            signature: def.header().to_string(),
+            conditions: conditions(&def.annotations),
+            description: doc.map_or(String::new(), |doc| join_lines(&doc.body, "\n")),
+            sections: doc.map_or(Vec::new(), |doc| {
+                doc.sections.iter()
+                    .map(|section| (section.name.as_str(), join_lines(&section.body, "\n")))
+                    .collect()
+            }),
+            see: doc.map_or(Vec::new(), |doc| doc.see().map(|name| linker.link(name)).collect()),
+            fields: def.members.iter()
+                .filter_map(|member| match member {
+                    MemberDefinition::Field(field) => Some(field),
+                    _ => None,
+                })
+                .map(|field| ClassField {
+                    name: field.name.as_str(),
+                    type_: linker.link_type(&field.type_),
+                    badges: field.qualifiers.iter()
+                        .map(|qualifier| format!("`{}`", qualifier))
+                        .chain(field.annotations.iter()
+                            .filter_map(Annotation::condition)
+                            .map(|condition| format!("`@if({})`", condition)))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    // Table cells can't span lines
+                    description: field.comments.as_ref().map_or(String::new(), |comments| join_lines(&comments.doc.body, " ")),
+                })
+                .collect(),
+            methods: def.members.iter()
+                .filter_map(|member| match member {
+                    MemberDefinition::Function(func) => Some(func),
+                    _ => None,
+                })
+                .map(|func| ClassMethod {
+                    name: func.name.as_str(),
+                    signature: func.to_string(),
+                    description: func.comments.as_ref().map_or(String::new(), |comments| join_lines(&comments.doc.body, "\n")),
+                })
+                .collect(),
+            source,
+            line: def.span.line,
+        }
+    }
+}
+
+impl DocumentationType for Class<'_> {
+    fn get_root_path() -> String {
+        String::from("/class")
+    }
+    fn get_type_name() -> String {
+        String::from("Class")
+    }
+    fn get_type_name_plural() -> String {
+        String::from("Classes")
+    }
+    fn get_type_icon() -> String {
+        String::from(":material-cube-outline:")
+    }
+    
+    fn get_path(&self) -> String {
+        format!("{}/{}",
+            Class::get_root_path(),
+            self.name
+        )
+    }
+    fn get_link(&self) -> String {
+        format!("[{}]({})",
+            self.name,
+            self.get_path()
+        )
+    }
+}
+#[derive(Debug, Clone, Copy, Template)]
+#[template(path="class-group.md")]
+pub struct ClassGroup<'a> {
+    pub name: &'a str,
+    pub classes: &'a Vec<Class<'a>>,
+}
+
+impl DocumentationType for ClassGroup<'_> {
+    fn get_root_path() -> String {
+        String::from("/class/group")
+    }
+    fn get_type_name() -> String {
+        String::from("Class Group")
+    }
+    fn get_type_name_plural() -> String {
+        String::from("Class Groups")
+    }
+    fn get_type_icon() -> String {
+        String::from(":material-view-list-outline:")
+    }
+
+    fn get_path(&self) -> String {
+        format!("{}/{}",
+            Class::get_root_path(),
+            self.name
+        )
+    }
+    fn get_link(&self) -> String {
+        format!("[{}]({:?})",
+            self.name,
+            self.get_path()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linker::PagePaths;
+    use parser::ast::AnyDefinition;
+
+    #[test]
+    fn link_base() {
+        let file = parser::parse_file("test.reds", "module Game\nimport Base.Entity\nclass Player extends Entity {}").unwrap();
+        let class = match file.defs.last() {
+            Some(AnyDefinition::Class(class)) => class,
+            def => panic!("expected a class, got {:?}", def),
+        };
+        let mut pages = PagePaths::new();
+        pages.insert("Base.Entity".to_string(), "/class/Base.Entity".to_string());
+        let source = SourceFile { filename: "test.reds", fullpath: "test.reds" };
+        let page = Class::new(class, source, Linker { file: &file, pages: &pages }).render().unwrap();
+
+        assert!(page.contains("Extends [`Entity`](/class/Base.Entity)"), "{}", page);
+    }
+}
@@ -1,21 +1,65 @@
 use askama::Template;
+use parser::ast::EnumDefinition;
 
-use crate::doctype::DocumentationType;
+use crate::doctype::{conditions, join_lines, DocumentationType};
+use crate::linker::Linker;
+use crate::sourcefile::SourceFile;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct EnumField<'a> {
     pub name: &'a str,
-    pub value: &'a str,
+    pub value: i64,
+    pub description: String,
 } 
 
-#[derive(Debug, Clone, Copy, Template)]
+#[derive(Debug, Clone, Template)]
 #[template(path="enum-full.md")]
 pub struct Enum<'a> {
-    pub name: &'a str,
-    pub fields: &'a Vec<EnumField<'a>>,
+    /// Name qualified with its module
+    pub name: String,
+    /// Module the enum belongs to, or empty
+    pub module: String,
+    /// Markdown for each condition the definition is only compiled under
+    pub conditions: Vec<String>,
+    pub description: String,
+    pub sections: Vec<(&'a str, String)>,
+    /// Markdown for each `@see` reference
+    pub see: Vec<String>,
+    pub fields: Vec<EnumField<'a>>,
+    pub source: SourceFile<'a>,
+    pub line: usize,
 } 
 
-impl<'a> DocumentationType for Enum<'_> {
+impl<'a> Enum<'a> {
+    pub fn new(def: &'a EnumDefinition, source: SourceFile<'a>, linker: Linker<'a>) -> Self {
+        let doc = def.comments.as_ref().map(|comments| &comments.doc);
+
+        Enum {
+            name: linker.file.qualify(def.name.as_str()),
+            module: linker.file.module.as_ref().map_or(String::new(), |module| module.name()),
+            conditions: conditions(&def.annotations),
+            description: doc.map_or(String::new(), |doc| join_lines(&doc.body, "\n")),
+            sections: doc.map_or(Vec::new(), |doc| {
+                doc.sections.iter()
+                    .map(|section| (section.name.as_str(), join_lines(&section.body, "\n")))
+                    .collect()
+            }),
+            see: doc.map_or(Vec::new(), |doc| doc.see().map(|name| linker.link(name)).collect()),
+            fields: def.values.iter()
+                .map(|value| EnumField {
+                    name: value.name.as_str(),
+                    value: value.value,
+                    // Table cells can't span lines
+                    description: value.comments.as_ref().map_or(String::new(), |comments| join_lines(&comments.doc.body, " ")),
+                })
+                .collect(),
+            source,
+            line: def.span.line,
+        }
+    }
+}
+
+impl DocumentationType for Enum<'_> {
     fn get_root_path() -> String {
         String::from("/enum")
     }
@@ -49,7 +93,7 @@ pub struct EnumGroup<'a> {
     pub enums: &'a Vec<Enum<'a>>,
 }
 
-impl<'a> DocumentationType for EnumGroup<'_> {
+impl DocumentationType for EnumGroup<'_> {
     fn get_root_path() -> String {
         String::from("/enum/group")
     }
@@ -0,0 +1,43 @@
+#ifndef REDSCRIPT_PARSER_H
+#define REDSCRIPT_PARSER_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Parses a buffer holding a script file, which may be UTF-8, or UTF-16 with a byte order mark.
+// Definitions are named as belonging to the file `name`, which may be null.
+//
+// Returns a NUL-terminated JSON document of the definitions and diagnostics,
+// `{"file": ..., "diagnostics": [...]}`, or `{"error": "..."}` if the buffer can't be decoded.
+// Returns null if `name` isn't UTF-8 or parsing failed unexpectedly. The result must be released
+// with `redscript_free`.
+//
+// # Safety
+//
+// `source` must point to `len` readable bytes, or may be null if `len` is 0, and `name` must be
+// null or point to a NUL-terminated string.
+char *redscript_parse(const char *name, const uint8_t *source, size_t len);
+
+// Releases a result returned by `redscript_parse`. Does nothing if `json` is null.
+//
+// # Safety
+//
+// `json` must be null, or a result of `redscript_parse` that hasn't already been released.
+void redscript_free(char *json);
+
+// Releases the identifier names kept from earlier calls to `redscript_parse`. Names are shared
+// between parses, so a host that parses many unrelated scripts should call this now and then,
+// such as once it's done with a batch of them.
+void redscript_release_names(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* REDSCRIPT_PARSER_H */
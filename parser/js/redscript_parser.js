@@ -0,0 +1,62 @@
+// JavaScript API of the parser built for wasm32-unknown-unknown:
+//
+//     cargo build -p parser --release --features capi --target wasm32-unknown-unknown
+//
+//     import { instantiate } from "./redscript_parser.js";
+//     const parser = await instantiate(await (await fetch("parser.wasm")).arrayBuffer());
+//     const { file, diagnostics } = parser.parse("class A extends B {}", "snippet.reds");
+//
+// Results are the JSON documents of redscript_parse in src/ffi.rs.
+
+// Instantiates the parser from the bytes of its wasm module
+export async function instantiate(bytes) {
+    const { instance } = await WebAssembly.instantiate(bytes, {});
+    const exports = instance.exports;
+    const encoder = new TextEncoder();
+    const decoder = new TextDecoder();
+
+    // Copies a string into the module's memory, which the caller must release
+    function pass(text) {
+        const bytes = encoder.encode(text);
+        const ptr = exports.redscript_alloc(bytes.length);
+        new Uint8Array(exports.memory.buffer, ptr, bytes.length).set(bytes);
+        return [ptr, bytes.length];
+    }
+
+    return {
+        // Parses a script into `{ file, diagnostics }`, where `name` is the file name it's
+        // parsed under. Throws if the script can't be parsed.
+        parse(source, name = "") {
+            const [namePtr, nameLen] = pass(name + "\0");
+            const [sourcePtr, sourceLen] = pass(source);
+            try {
+                const json = exports.redscript_parse(namePtr, sourcePtr, sourceLen);
+                if (json === 0) {
+                    throw new Error("redscript_parse failed");
+                }
+                try {
+                    const memory = new Uint8Array(exports.memory.buffer);
+                    const end = memory.indexOf(0, json);
+                    const result = JSON.parse(decoder.decode(memory.subarray(json, end)));
+                    if (result.error !== undefined) {
+                        throw new Error(result.error);
+                    }
+                    return result;
+                }
+                finally {
+                    exports.redscript_free(json);
+                }
+            }
+            finally {
+                exports.redscript_dealloc(sourcePtr, sourceLen);
+                exports.redscript_dealloc(namePtr, nameLen);
+            }
+        },
+
+        // Releases the identifier names kept from earlier parses, which a page that parses many
+        // unrelated scripts should do now and then
+        releaseNames() {
+            exports.redscript_release_names();
+        },
+    };
+}
@@ -1,6 +1,15 @@
-use std::rc::Rc;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::doc::{trim_blank_lines, DocComment};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AnyDefinition {
     Comments(CommentDefinition),
     Enum(EnumDefinition),
@@ -10,34 +19,160 @@ pub enum AnyDefinition {
     Param(ParamDefinition),
     Field(FieldDefinition),
     File(FileDefinition),
+    Module(ModuleDefinition),
+    Import(ImportDefinition),
 }
 
-#[derive(Debug)]
-pub enum Ident {
-    Static(&'static str),
-    Owned(Rc<String>),
+/// Location of a node within its source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset one past the last character
+    pub end: usize,
+    /// 1-based line of the first character
+    pub line: usize,
+    /// 1-based column of the first character
+    pub column: usize,
+}
+
+impl Span {
+    pub const fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span { start, end, line, column }
+    }
+}
+
+/// Interned identifier. Each distinct name is stored once, so identifiers are cheap to clone,
+/// compare and hash, and can be used as keys in symbol tables.
+#[derive(Clone)]
+pub struct Ident(Arc<str>);
+
+/// Number of separately locked parts of the interned names, so threads parsing files in parallel
+/// seldom wait on each other
+const SHARDS: usize = 64;
+
+/// Every name interned and not yet released, each in the shard picked by its hash
+static INTERNED: OnceLock<[Mutex<HashSet<Arc<str>>>; SHARDS]> = OnceLock::new();
+
+fn lock(shard: &Mutex<HashSet<Arc<str>>>) -> MutexGuard<'_, HashSet<Arc<str>>> {
+    shard.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn shards() -> &'static [Mutex<HashSet<Arc<str>>>; SHARDS] {
+    INTERNED.get_or_init(|| std::array::from_fn(|_| Mutex::default()))
+}
+
+/// Shard that holds `name` if it's interned
+fn interned(name: &str) -> MutexGuard<'static, HashSet<Arc<str>>> {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    lock(&shards()[hasher.finish() as usize % SHARDS])
 }
 
 impl Ident {
-    pub fn new(str: String) -> Ident {
-        Ident::Owned(Rc::new(str))
+    pub fn new(str: &str) -> Ident {
+        let mut interned = interned(str);
+        match interned.get(str) {
+            Some(name) => Ident(name.clone()),
+            None => {
+                let name: Arc<str> = Arc::from(str);
+                interned.insert(name.clone());
+                Ident(name)
+            }
+        }
     }
 
-    pub fn to_owned(&self) -> Rc<String> {
-        match self {
-            Ident::Static(str) => Rc::new(str.to_string()),
-            Ident::Owned(rc) => rc.clone(),
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Releases the names that no identifier uses anymore. Interned names are otherwise kept for
+    /// the life of the program, so a long-running host that parses many unrelated scripts, such
+    /// as a user of the C API, should call this once it has dropped their definitions.
+    pub fn release_unused() {
+        // Only the shard itself holds an unused name, and new identifiers can't take one while the
+        // shard is locked
+        for shard in shards() {
+            lock(shard).retain(|name| Arc::strong_count(name) > 1);
         }
     }
 }
 
+/// Interned names are equal only if they're the same name
+impl PartialEq for Ident {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Ident {}
+
+/// Hashed the same as the name, so maps keyed by identifiers can be looked up by `&str`
+impl Hash for Ident {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+/// Ordered by name, rather than by when each name was interned
+impl Ord for Ident {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Ident {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Borrow<str> for Ident {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Ident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Ident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Identifiers serialize as plain strings
+#[cfg(feature = "serde")]
+impl serde::Serialize for Ident {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Ident {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|name| Ident::new(&name))
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScalarType {
     Void,
     Variant,
     Bool,
+    I8,
+    I16,
     I32,
     I64,
+    U8,
+    U16,
     U32,
     U64,
     F32,
@@ -46,17 +181,30 @@ pub enum ScalarType {
     CName,
     Resource,
     TweakDBID,
+    LocalizationString,
+    NodeRef,
+    CRUID,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ContainerType {
     Array,
+    /// Fixed size array, `[Type; N]`
+    StaticArray(usize),
+    /// Strong reference, `ref<Type>` or its `handle<Type>` alias
     Ref,
+    /// Weak reference, `wref<Type>` or its `whandle<Type>` alias
     WeakRef,
     ScriptRef,
+    /// Resource reference, `rRef<Type>`
+    ResourceRef,
+    /// Asynchronously loaded resource reference, `raRef<Type>`
+    ResourceAsyncRef,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VariableType {
     Scalar(ScalarType),
     Compound(Ident),
@@ -64,63 +212,197 @@ pub enum VariableType {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeDeclaration {
     pub type_: VariableType,
-    pub subtype: Option<Box<TypeDeclaration>>
+    pub subtype: Option<Box<TypeDeclaration>>,
+    /// Span of the type as written, which for a compound type is its name, or none for a type
+    /// that isn't written, such as the `Void` return of a function without a return type
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for ScalarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScalarType::Void => "Void",
+            ScalarType::Variant => "Variant",
+            ScalarType::Bool => "Bool",
+            ScalarType::I8 => "Int8",
+            ScalarType::I16 => "Int16",
+            ScalarType::I32 => "Int32",
+            ScalarType::I64 => "Int64",
+            ScalarType::U8 => "Uint8",
+            ScalarType::U16 => "Uint16",
+            ScalarType::U32 => "Uint32",
+            ScalarType::U64 => "Uint64",
+            ScalarType::F32 => "Float",
+            ScalarType::F64 => "Double",
+            ScalarType::String => "String",
+            ScalarType::CName => "CName",
+            ScalarType::Resource => "ResRef",
+            ScalarType::TweakDBID => "TweakDBID",
+            ScalarType::LocalizationString => "LocalizationString",
+            ScalarType::NodeRef => "NodeRef",
+            ScalarType::CRUID => "CRUID",
+        })
+    }
+}
+
+/// Types display as redscript source, using the canonical keyword for aliased containers
+impl fmt::Display for TypeDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let container = match &self.type_ {
+            VariableType::Scalar(scalar) => return write!(f, "{}", scalar),
+            VariableType::Compound(name) => return f.write_str(name.as_str()),
+            VariableType::Container(container) => container,
+        };
+        // Only an AST built or deserialised by hand can have a container without a subtype
+        let subtype: &dyn fmt::Display = match &self.subtype {
+            Some(subtype) => subtype,
+            None => &"?",
+        };
+        match container {
+            ContainerType::Array => write!(f, "array<{}>", subtype),
+            ContainerType::StaticArray(size) => write!(f, "[{}; {}]", subtype, size),
+            ContainerType::Ref => write!(f, "ref<{}>", subtype),
+            ContainerType::WeakRef => write!(f, "wref<{}>", subtype),
+            ContainerType::ScriptRef => write!(f, "script_ref<{}>", subtype),
+            ContainerType::ResourceRef => write!(f, "rRef<{}>", subtype),
+            ContainerType::ResourceAsyncRef => write!(f, "raRef<{}>", subtype),
+        }
+    }
 }
 
 impl TypeDeclaration {
     pub const fn leaf(type_: VariableType) -> Self {
-        TypeDeclaration { type_, subtype: None }
+        TypeDeclaration { type_, subtype: None, span: None }
     }
 
     pub fn node(type_: VariableType, subtype: TypeDeclaration) -> Self {
-        TypeDeclaration { type_, subtype: Some(Box::new(subtype)) }
+        TypeDeclaration { type_, subtype: Some(Box::new(subtype)), span: None }
     }
 }
 
+/// Argument of an annotation
 #[derive(Debug)]
-pub enum Annotation {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnnotationArg {
+    Ident(Ident),
+    /// String literal, with escape sequences kept as written
+    String(Arc<str>),
+    /// Name literal, `n"..."`
+    Name(Arc<str>),
+    /// Number as written, including any sign or type suffix
+    Number(Arc<str>),
+    Bool(bool),
+    /// Negated condition, `!ModuleExists("...")`
+    Not(Box<AnnotationArg>),
+    Call(Ident, Vec<AnnotationArg>),
+    /// Arguments that couldn't be parsed, kept whole as written so an annotation never makes its
+    /// definition fail
+    Raw(Arc<str>),
+}
+
+/// Arguments display as written in the source
+impl fmt::Display for AnnotationArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotationArg::Ident(name) => f.write_str(name.as_str()),
+            AnnotationArg::String(text) => write!(f, "\"{}\"", text),
+            AnnotationArg::Name(text) => write!(f, "n\"{}\"", text),
+            AnnotationArg::Number(text) | AnnotationArg::Raw(text) => f.write_str(text),
+            AnnotationArg::Bool(value) => write!(f, "{}", value),
+            AnnotationArg::Not(arg) => write!(f, "!{}", arg),
+            AnnotationArg::Call(name, args) => {
+                write!(f, "{}(", name.as_str())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnnotationKind {
     ReplaceGlobal,
     ReplaceMethod(Ident),
     AddMethod(Ident),
     AddField(Ident),
-    Unsupported(Ident, Rc<String>)
+    WrapMethod(Ident),
+    /// Conditional compilation, `@if(ModuleExists("..."))`
+    If(AnnotationArg),
+    Unsupported(Ident, Vec<AnnotationArg>)
 }
 
-/// Object visibility shared by classes and members
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Annotation {
+    pub kind: AnnotationKind,
+    /// Span of the identifier in the annotation, which is the class named by `@replaceMethod`,
+    /// `@addMethod`, `@addField` and `@wrapMethod`, or the name of an unsupported annotation
+    pub ident_span: Option<Span>,
+    pub span: Span,
+}
+
+impl Annotation {
+    /// Condition the annotated definition is only compiled under, if any
+    pub fn condition(&self) -> Option<&AnnotationArg> {
+        match &self.kind {
+            AnnotationKind::If(condition) => Some(condition),
+            _ => None,
+        }
+    }
+}
+
+/// Object visibility shared by classes and members. Definitions without a visibility written are
+/// private, and have `None` as theirs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Visibility {
     Public,
     Protected,
     Private,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ClassQualifier {
     Abstract,
+    ImportOnly,
     Struct,
     Native,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FuncQualifier {
     Abstract,
     Callback,
     Const,
     Exec,
     Final,
+    ImportOnly,
     Native,
+    Quest,
     Static,
+    Unsafe,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParamQualifier {
     Out,
     Optional,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FieldQualifier {
     Const,
     Edit,
@@ -130,7 +412,70 @@ pub enum FieldQualifier {
     Static,
 }
 
+// Modifiers display as the keyword they're written with
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Visibility::Public => "public",
+            Visibility::Protected => "protected",
+            Visibility::Private => "private",
+        })
+    }
+}
+
+impl fmt::Display for ClassQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ClassQualifier::Abstract => "abstract",
+            ClassQualifier::ImportOnly => "importonly",
+            ClassQualifier::Struct => "struct",
+            ClassQualifier::Native => "native",
+        })
+    }
+}
+
+impl fmt::Display for FuncQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FuncQualifier::Abstract => "abstract",
+            FuncQualifier::Callback => "cb",
+            FuncQualifier::Const => "const",
+            FuncQualifier::Exec => "exec",
+            FuncQualifier::Final => "final",
+            FuncQualifier::ImportOnly => "importonly",
+            FuncQualifier::Native => "native",
+            FuncQualifier::Quest => "quest",
+            FuncQualifier::Static => "static",
+            FuncQualifier::Unsafe => "unsafe",
+        })
+    }
+}
+
+impl fmt::Display for ParamQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParamQualifier::Out => "out",
+            ParamQualifier::Optional => "opt",
+        })
+    }
+}
+
+impl fmt::Display for FieldQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldQualifier::Const => "const",
+            FieldQualifier::Edit => "edit",
+            FieldQualifier::Final => "final",
+            FieldQualifier::Native => "native",
+            FieldQualifier::Persistent => "persistent",
+            FieldQualifier::Static => "static",
+        })
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MemberDefinition {
     Field(FieldDefinition),
     Function(FuncDefinition),
@@ -140,64 +485,315 @@ pub enum MemberDefinition {
 // Definitions
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CommentDefinition {
-    pub lines: Vec<Rc<String>>,
+    pub lines: Vec<Arc<str>>,
+    pub doc: DocComment,
+    pub span: Span,
+}
+
+impl CommentDefinition {
+    /// Builds a comment from its lines, with leading and trailing blank lines removed
+    pub fn new(mut lines: Vec<Arc<str>>, span: Span) -> Self {
+        trim_blank_lines(&mut lines);
+        let doc = DocComment::parse(&lines);
+        CommentDefinition { lines, doc, span }
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EnumDefinition {
     pub comments: Option<CommentDefinition>,
+    pub annotations: Vec<Annotation>,
     pub name: Ident,
+    pub name_span: Span,
     pub values: Vec<EnumValDefinition>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EnumValDefinition {
     pub comments: Option<CommentDefinition>,
     pub name: Ident,
+    pub name_span: Span,
     pub value: i64,
+    pub span: Span,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClassDefinition {
     pub comments: Option<CommentDefinition>,
-    pub visibility: Visibility,
+    pub annotations: Vec<Annotation>,
+    pub visibility: Option<Visibility>,
     pub qualifiers: Vec<ClassQualifier>,
     pub name: Ident,
+    pub name_span: Span,
     pub base: Option<Ident>,
+    pub base_span: Option<Span>,
     pub members: Vec<MemberDefinition>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncDefinition {
     pub comments: Option<CommentDefinition>,
     pub annotations: Vec<Annotation>,
-    pub visibility: Visibility,
+    pub visibility: Option<Visibility>,
     pub qualifiers: Vec<FuncQualifier>,
     pub name: Ident,
+    pub name_span: Span,
     pub params: Vec<ParamDefinition>,
     pub returns: TypeDeclaration,
+    /// Span of the body from brace to brace, or none if the function is only declared
+    pub body_span: Option<Span>,
+    /// Statements of the body, which are only parsed on request by [`crate::parse_bodies`]
+    pub body: Option<Vec<Statement>>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParamDefinition {
     pub qualifiers: Vec<ParamQualifier>,
     pub name: Ident,
+    pub name_span: Span,
     pub type_: TypeDeclaration,
+    pub span: Span,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FieldDefinition {
     pub comments: Option<CommentDefinition>,
     pub annotations: Vec<Annotation>,
-    pub visibility: Visibility,
+    pub visibility: Option<Visibility>,
     pub qualifiers: Vec<FieldQualifier>,
     pub name: Ident,
+    pub name_span: Span,
     pub type_: TypeDeclaration,
+    pub span: Span,
+}
+
+/// `module` header naming the module that every definition in a file belongs to
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModuleDefinition {
+    pub comments: Option<CommentDefinition>,
+    pub path: Vec<Ident>,
+    pub span: Span,
+}
+
+impl ModuleDefinition {
+    /// Module path joined with dots, as written in the source
+    pub fn name(&self) -> String {
+        join_path(&self.path)
+    }
 }
 
+/// What an `import` brings into scope from its path
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImportKind {
+    /// `import Foo.Bar`, the single definition named by the path
+    Exact,
+    /// `import Foo.*`, every definition in the module
+    All,
+    /// `import Foo.{Bar, Baz}`, the listed definitions in the module
+    Selected(Vec<Ident>),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportDefinition {
+    pub path: Vec<Ident>,
+    pub kind: ImportKind,
+    pub span: Span,
+}
+
+impl ImportDefinition {
+    /// Fully qualified name that `name` refers to if this import brings it into scope
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        match &self.kind {
+            ImportKind::Exact => match self.path.last() {
+                Some(last) if last.as_str() == name => Some(join_path(&self.path)),
+                _ => None,
+            },
+            ImportKind::All => Some(format!("{}.{}", join_path(&self.path), name)),
+            ImportKind::Selected(names) if names.iter().any(|n| n.as_str() == name) =>
+                Some(format!("{}.{}", join_path(&self.path), name)),
+            ImportKind::Selected(_) => None,
+        }
+    }
+}
+
+fn join_path(path: &[Ident]) -> String {
+    path.iter().map(Ident::as_str).collect::<Vec<_>>().join(".")
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileDefinition {
     pub name: Ident,
+    /// Documentation of the file itself, from an `@file` comment
+    pub comments: Option<CommentDefinition>,
+    pub module: Option<ModuleDefinition>,
     pub defs: Vec<AnyDefinition>
 }
+
+impl FileDefinition {
+    pub fn imports(&self) -> impl Iterator<Item = &ImportDefinition> {
+        self.defs.iter().filter_map(|def| match def {
+            AnyDefinition::Import(import) => Some(import),
+            _ => None,
+        })
+    }
+
+    /// Qualifies the name of a definition in this file with the file's module, if it has one
+    pub fn qualify(&self, name: &str) -> String {
+        match &self.module {
+            Some(module) => format!("{}.{}", module.name(), name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Resolves a name used within this file to the fully qualified name of a definition for which
+    /// `exists` returns true. Definitions in the file's own module are preferred, followed by those
+    /// brought into scope by imports in the order written, and then the name as written.
+    pub fn resolve(&self, name: &str, exists: impl Fn(&str) -> bool) -> Option<String> {
+        std::iter::once(self.qualify(name))
+            .chain(self.imports().filter_map(|import| import.resolve(name)))
+            .chain(std::iter::once(name.to_string()))
+            .find(|qualified| exists(qualified))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+// Function bodies
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatementKind {
+    /// Local variable, whose type may be left to be inferred from its value
+    Let { name: Ident, type_: Option<TypeDeclaration>, value: Option<Expression> },
+    Expression(Expression),
+    Return(Option<Expression>),
+    Break,
+    Continue,
+    /// An `else if` is an `else` block holding only the nested `if`
+    If { condition: Expression, then: Vec<Statement>, else_: Option<Vec<Statement>> },
+    /// Cases in the order written, the `default` among them, as each falls through to the next
+    Switch { value: Expression, cases: Vec<SwitchCase> },
+    While { condition: Expression, body: Vec<Statement> },
+    /// Loop over the elements of an array
+    For { name: Ident, array: Expression, body: Vec<Statement> },
+}
+
+/// `case` of a `switch`, which falls through to the next case unless it breaks or returns
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwitchCase {
+    /// Value of the case, or none for the `default` case
+    pub label: Option<Expression>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExpressionKind {
+    /// Local variable, parameter, field, function or class, depending on where the name resolves
+    Ident(Ident),
+    Constant(Constant),
+    This,
+    Super,
+    Null,
+    Array(Vec<Expression>),
+    Member(Box<Expression>, Ident),
+    Index(Box<Expression>, Box<Expression>),
+    /// Call of a function or method, with any type arguments written as in `Cast<Float>(x)`
+    Call { callee: Box<Expression>, type_args: Vec<TypeDeclaration>, args: Vec<Expression> },
+    /// Instance of a class, or of a struct from its field values in order
+    New(Ident, Vec<Expression>),
+    /// `as` cast of an object to a class
+    Cast(Box<Expression>, TypeDeclaration),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+    /// Assignment, along with the operator of a compound assignment such as `+=`
+    Assign(Option<BinaryOp>, Box<Expression>, Box<Expression>),
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Constant {
+    Bool(bool),
+    /// Number as written, including any type suffix such as `1.0d` or `5u`
+    Number(Arc<str>),
+    /// String contents are kept as written, escape sequences included
+    String(StringKind, Arc<str>),
+}
+
+/// Type of a string literal, given by its prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StringKind {
+    String,
+    /// `n""`
+    Name,
+    /// `r""`
+    Resource,
+    /// `t""`
+    TweakDBID,
+    /// `l""`
+    LocalizationString,
+    /// `s""`, whose `\(...)` interpolations are kept as written rather than parsed
+    Interpolated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
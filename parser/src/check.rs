@@ -0,0 +1,119 @@
+use std::fmt::Display;
+
+use crate::ast::{
+    ClassDefinition, ClassQualifier, FieldDefinition, FieldQualifier, FileDefinition, FuncDefinition, FuncQualifier,
+    Ident, Span
+};
+use crate::diagnostic::Diagnostic;
+use crate::visit::{walk_class, Visitor};
+
+/// Class qualifiers that make no sense together, with the reason why
+const CLASS_CONFLICTS: &[(ClassQualifier, ClassQualifier, &str)] = &[
+    (ClassQualifier::Abstract, ClassQualifier::Struct, "a struct can't be extended"),
+];
+
+/// Function qualifiers that make no sense together, with the reason why
+const FUNC_CONFLICTS: &[(FuncQualifier, FuncQualifier, &str)] = &[
+    (FuncQualifier::Abstract, FuncQualifier::Final, "an abstract function has to be overridden"),
+    (FuncQualifier::Abstract, FuncQualifier::Static, "a static function can't be overridden"),
+];
+
+/// Field qualifiers that make no sense together, with the reason why
+const FIELD_CONFLICTS: &[(FieldQualifier, FieldQualifier, &str)] = &[
+    (FieldQualifier::Const, FieldQualifier::Static, "a constant is already shared by every instance"),
+    (FieldQualifier::Const, FieldQualifier::Edit, "a constant can't be changed in the editor"),
+    (FieldQualifier::Const, FieldQualifier::Persistent, "a constant never needs to be saved"),
+    (FieldQualifier::Static, FieldQualifier::Edit, "only instance fields are shown in the editor"),
+    (FieldQualifier::Static, FieldQualifier::Persistent, "only instance fields are saved with the game"),
+];
+
+struct Checker(Vec<Diagnostic>);
+
+impl Checker {
+    /// Reports each conflicting pair of qualifiers of a definition, located at its name
+    fn check_qualifiers<Q: PartialEq + Display>(
+        &mut self,
+        kind: &str,
+        name: &Ident,
+        name_span: Span,
+        qualifiers: &[Q],
+        conflicts: &[(Q, Q, &str)]
+    ) {
+        for (a, b, reason) in conflicts {
+            if qualifiers.contains(a) && qualifiers.contains(b) {
+                self.0.push(Diagnostic::warning(
+                    format!("{} `{}` is both `{}` and `{}`, but {}", kind, name.as_str(), a, b, reason),
+                    name_span
+                ));
+            }
+        }
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_class(&mut self, class: &ClassDefinition) {
+        self.check_qualifiers("class", &class.name, class.name_span, &class.qualifiers, CLASS_CONFLICTS);
+        walk_class(self, class);
+    }
+    fn visit_func(&mut self, func: &FuncDefinition) {
+        self.check_qualifiers("func", &func.name, func.name_span, &func.qualifiers, FUNC_CONFLICTS);
+    }
+    fn visit_field(&mut self, field: &FieldDefinition) {
+        self.check_qualifiers("field", &field.name, field.name_span, &field.qualifiers, FIELD_CONFLICTS);
+    }
+}
+
+/// Checks the qualifiers of every definition in a file for combinations with no meaning, which
+/// the grammar accepts as written
+pub fn check_file(file: &FileDefinition) -> Vec<Diagnostic> {
+    let mut checker = Checker(Vec::new());
+    checker.visit_file(file);
+    checker.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AnyDefinition, MemberDefinition};
+
+    #[test]
+    fn check_field_qualifiers() {
+        let file = crate::parse_file("test.reds", "
+public class Save {
+    public persistent let m_kept: Int32;
+    public static persistent let m_lost: Int32;
+    public const edit let m_fixed: Float;
+}").unwrap();
+
+        let field = match &file.defs[0] {
+            AnyDefinition::Class(class) => match &class.members[0] {
+                MemberDefinition::Field(field) => field,
+                member => panic!("expected a field, got {:?}", member),
+            },
+            def => panic!("expected a class, got {:?}", def),
+        };
+        assert_eq!(field.qualifiers, vec![FieldQualifier::Persistent]);
+
+        let messages: Vec<String> = check_file(&file).into_iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "4:34: warning: field `m_lost` is both `static` and `persistent`, but only instance fields are saved with the game",
+            "5:27: warning: field `m_fixed` is both `const` and `edit`, but a constant can't be changed in the editor",
+        ]);
+    }
+
+    #[test]
+    fn check_class_func_qualifiers() {
+        let file = crate::parse_file("test.reds", "
+abstract struct Data {}
+public abstract class Base {
+    public abstract final func Run();
+    public static func Make() -> ref<Base>;
+}").unwrap();
+
+        let messages: Vec<String> = check_file(&file).into_iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "2:17: warning: class `Data` is both `abstract` and `struct`, but a struct can't be extended",
+            "4:32: warning: func `Run` is both `abstract` and `final`, but an abstract function has to be overridden",
+        ]);
+    }
+}
@@ -0,0 +1,461 @@
+//! Lossless concrete syntax tree, which keeps every token of a file along with the whitespace and
+//! comments between them, so it reproduces its source byte for byte.
+//!
+//! Top-level items are found by the same grammar as the [`ast`](crate::ast). Lowering the tree
+//! into its definitions parses the text of each item's tokens again with that grammar, rather than
+//! reading the definitions off the tokens.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use crate::ast::{AnyDefinition, FileDefinition};
+use crate::diagnostic::Diagnostic;
+use crate::source::LineIndex;
+use crate::{finish_file, redscript, skipped_item};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Spaces, tabs, line endings and a byte order mark
+    Whitespace,
+    /// `//` comment, up to the end of its line
+    LineComment,
+    /// `/* */` comment
+    BlockComment,
+    /// `///` documentation comment, up to the end of its line
+    DocLineComment,
+    /// `/** */` documentation comment
+    DocBlockComment,
+    Ident,
+    /// Decimal or hexadecimal integer, without any sign
+    Number,
+    /// String literal, including its quotes
+    String,
+    /// Name literal, `n"..."`
+    Name,
+    /// `->`, or any other single character
+    Punct,
+}
+
+impl TokenKind {
+    /// Whether tokens of this kind are ignored by the grammar, other than for documentation
+    pub fn is_trivia(self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Byte offset of the token within the source
+    pub offset: usize,
+}
+
+/// Length in bytes of the prefix of `text` up to, but not including, the first match of `end`
+fn len_until(text: &str, end: impl Fn(char) -> bool) -> usize {
+    text.find(end).unwrap_or(text.len())
+}
+
+/// Length of a line comment, which stops before a CRLF or LF line ending
+fn line_len(text: &str) -> usize {
+    let len = len_until(text, |c| c == '\n');
+    if text[..len].ends_with('\r') { len - 1 } else { len }
+}
+
+/// Length of a block comment, including its closing `*/` unless it's unterminated
+fn block_len(text: &str) -> usize {
+    text[2..].find("*/").map_or(text.len(), |i| i + 4)
+}
+
+/// Length of a string literal starting at its opening quote, including the closing quote unless
+/// it's unterminated
+fn string_len(text: &str) -> usize {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => { chars.next(); }
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Splits source text into tokens, which together cover all of it
+pub fn lex(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+
+    while offset < source.len() {
+        let rest = &source[offset..];
+        let first = rest.chars().next().unwrap();
+        let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+        let (kind, len) = if matches!(first, ' ' | '\t' | '\r' | '\n' | '\u{FEFF}') {
+            (TokenKind::Whitespace, len_until(rest, |c| !matches!(c, ' ' | '\t' | '\r' | '\n' | '\u{FEFF}')))
+        }
+        // Separator lines of four or more slashes are ordinary comments
+        else if rest.starts_with("///") && !rest.starts_with("////") {
+            (TokenKind::DocLineComment, line_len(rest))
+        }
+        else if rest.starts_with("//") {
+            (TokenKind::LineComment, line_len(rest))
+        }
+        // The empty `/**/` is an ordinary comment
+        else if rest.starts_with("/**") && !rest.starts_with("/**/") {
+            (TokenKind::DocBlockComment, block_len(rest))
+        }
+        else if rest.starts_with("/*") {
+            (TokenKind::BlockComment, block_len(rest))
+        }
+        else if rest.starts_with("n\"") {
+            (TokenKind::Name, 1 + string_len(&rest[1..]))
+        }
+        else if first == '"' {
+            (TokenKind::String, string_len(rest))
+        }
+        else if first.is_ascii_digit() {
+            (TokenKind::Number, len_until(rest, |c| !is_ident(c)))
+        }
+        else if first.is_ascii_alphabetic() || first == '_' {
+            (TokenKind::Ident, len_until(rest, |c| !is_ident(c)))
+        }
+        else if rest.starts_with("->") {
+            (TokenKind::Punct, 2)
+        }
+        else {
+            (TokenKind::Punct, first.len_utf8())
+        };
+
+        tokens.push(Token { kind, text: &rest[..len], offset });
+        offset += len;
+    }
+
+    tokens
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Module,
+    Import,
+    Enum,
+    Class,
+    Func,
+    Field,
+    /// Free-standing comments
+    Comments,
+    /// Top-level item that couldn't be parsed
+    Skipped,
+    /// Curly-braced block within an item, such as a class body
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element<'a> {
+    Node(Node<'a>),
+    Token(Token<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node<'a> {
+    pub kind: NodeKind,
+    /// Byte offset of the start of the node within the source
+    pub start: usize,
+    /// Byte offset one past the end of the node
+    pub end: usize,
+    pub children: Vec<Element<'a>>,
+}
+
+impl<'a> Node<'a> {
+    /// Every token within the node, in source order
+    pub fn tokens(&self) -> Vec<&Token<'a>> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'n>(&'n self, tokens: &mut Vec<&'n Token<'a>>) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.collect_tokens(tokens),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// Child nodes, without the tokens between them
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+}
+
+/// Nodes display as their exact source text
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(token.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hands out tokens in order, splitting any that cross a requested boundary
+struct TokenCursor<'a> {
+    tokens: std::vec::IntoIter<Token<'a>>,
+    pending: Option<Token<'a>>,
+}
+
+impl<'a> TokenCursor<'a> {
+    fn next_before(&mut self, end: usize) -> Option<Token<'a>> {
+        let token = self.pending.take().or_else(|| self.tokens.next())?;
+        if token.offset >= end {
+            self.pending = Some(token);
+            return None;
+        }
+
+        let len = end - token.offset;
+        if len < token.text.len() {
+            let (head, tail) = token.text.split_at(len);
+            self.pending = Some(Token { kind: token.kind, text: tail, offset: end });
+            return Some(Token { text: head, ..token });
+        }
+        Some(token)
+    }
+
+    /// Elements up to `end`, with curly-braced blocks grouped into nodes
+    fn elements(&mut self, end: usize) -> Vec<Element<'a>> {
+        let mut elements = Vec::new();
+        while let Some(token) = self.next_before(end) {
+            if token.kind == TokenKind::Punct && token.text == "{" {
+                elements.push(Element::Node(self.block(token, end)));
+            }
+            else {
+                elements.push(Element::Token(token));
+            }
+        }
+        elements
+    }
+
+    /// Block opened by the given token, closed by its matching `}` or else the end of the item
+    fn block(&mut self, open: Token<'a>, end: usize) -> Node<'a> {
+        let mut children = vec![Element::Token(open)];
+        while let Some(token) = self.next_before(end) {
+            let close = token.kind == TokenKind::Punct && token.text == "}";
+            if token.kind == TokenKind::Punct && token.text == "{" {
+                children.push(Element::Node(self.block(token, end)));
+            }
+            else {
+                children.push(Element::Token(token));
+            }
+            if close {
+                break;
+            }
+        }
+
+        let last = match children.last() {
+            Some(Element::Token(token)) => token.offset + token.text.len(),
+            Some(Element::Node(node)) => node.end,
+            None => open.offset + 1,
+        };
+        Node { kind: NodeKind::Block, start: open.offset, end: last, children }
+    }
+}
+
+fn item_kind(def: &Option<AnyDefinition>) -> NodeKind {
+    match def {
+        Some(AnyDefinition::Module(_)) => NodeKind::Module,
+        Some(AnyDefinition::Import(_)) => NodeKind::Import,
+        Some(AnyDefinition::Enum(_)) => NodeKind::Enum,
+        Some(AnyDefinition::Class(_)) => NodeKind::Class,
+        Some(AnyDefinition::Func(_)) => NodeKind::Func,
+        Some(AnyDefinition::Field(_)) => NodeKind::Field,
+        Some(AnyDefinition::Comments(_)) => NodeKind::Comments,
+        _ => NodeKind::Skipped,
+    }
+}
+
+/// Concrete syntax tree of a whole script file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree<'a> {
+    pub source: &'a str,
+    /// File node, whose children are the top-level items and the trivia between them
+    pub root: Node<'a>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Builds the tree of a script file. Like [`parse_file_recovering`](crate::parse_file_recovering),
+    /// this never fails, as items that can't be parsed become `Skipped` nodes.
+    pub fn parse(source: &'a str) -> Self {
+        let index = LineIndex::new(source);
+        // The rule can always fall back to skipping, so only a bug in the grammar would make it fail
+        let items = redscript::file_items(source, &index, &RefCell::default()).expect("file items should always parse");
+
+        let mut cursor = TokenCursor { tokens: lex(source).into_iter(), pending: None };
+        let mut children = Vec::new();
+        for (start, end, def) in items {
+            while let Some(token) = cursor.next_before(start) {
+                children.push(Element::Token(token));
+            }
+            children.push(Element::Node(Node { kind: item_kind(&def), start, end, children: cursor.elements(end) }));
+        }
+        while let Some(token) = cursor.next_before(source.len()) {
+            children.push(Element::Token(token));
+        }
+
+        SyntaxTree { source, root: Node { kind: NodeKind::File, start: 0, end: source.len(), children } }
+    }
+
+    /// Lowers the tree into the definitions of the file by parsing the text of each top-level
+    /// item's tokens, so edits to the tokens or node kinds are reflected, while the `source`,
+    /// `start` and `end` the tree was built with are ignored. For an unchanged tree this returns
+    /// the same definitions and diagnostics as
+    /// [`parse_file_recovering`](crate::parse_file_recovering) would for its source.
+    pub fn lower(&self, name: &str) -> (FileDefinition, Vec<Diagnostic>) {
+        let text = self.to_string();
+        let index = LineIndex::new(&text);
+        let reported = RefCell::default();
+
+        let mut defs = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut skipped = Vec::new();
+        let mut offset = 0;
+        for child in &self.root.children {
+            let item = match child {
+                Element::Node(item) => item,
+                Element::Token(token) => {
+                    offset += token.text.len();
+                    continue;
+                }
+            };
+            let start = offset;
+            offset += item.tokens().iter().map(|token| token.text.len()).sum::<usize>();
+
+            let parsed = match item.kind {
+                NodeKind::Skipped => None,
+                _ => redscript::definition_at(&text, &index, &reported, &(start..offset)).ok(),
+            };
+            match parsed {
+                Some(def) => defs.push(def),
+                None => {
+                    diagnostics.push(skipped_item(&text, &index, start, offset));
+                    skipped.push(start..offset);
+                }
+            }
+        }
+
+        finish_file(name, defs, diagnostics, reported, &skipped)
+    }
+}
+
+/// The tree displays as the exact source text it was built from
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lex_kinds() {
+        let kinds: Vec<(TokenKind, &str)> = lex("//////\n/// Doc\r\nlet m_a: array<Int32> = n\"x\"; // note\n/**/ -> 0x1F")
+            .into_iter()
+            .filter(|token| token.kind != TokenKind::Whitespace)
+            .map(|token| (token.kind, token.text))
+            .collect();
+
+        assert_eq!(kinds, vec![
+            (TokenKind::LineComment, "//////"),
+            (TokenKind::DocLineComment, "/// Doc"),
+            (TokenKind::Ident, "let"),
+            (TokenKind::Ident, "m_a"),
+            (TokenKind::Punct, ":"),
+            (TokenKind::Ident, "array"),
+            (TokenKind::Punct, "<"),
+            (TokenKind::Ident, "Int32"),
+            (TokenKind::Punct, ">"),
+            (TokenKind::Punct, "="),
+            (TokenKind::Name, "n\"x\""),
+            (TokenKind::Punct, ";"),
+            (TokenKind::LineComment, "// note"),
+            (TokenKind::BlockComment, "/**/"),
+            (TokenKind::Punct, "->"),
+            (TokenKind::Number, "0x1F"),
+        ]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let sources = [
+            "",
+            "\u{FEFF}/// Doc\r\nenum A { B = 0 }\r\n",
+            "module Mod\nimport Other.*\n\n/// Class\npublic class C extends B {\n  // note\n  public func F() { if x { \"}\" } }\n}\n",
+            "enum Ä { Ö = 1 }\n\npublic class Broken extends {\n}\n/* unterminated",
+            "func Unclosed() {",
+        ];
+
+        for source in &sources {
+            let tree = SyntaxTree::parse(source);
+            assert_eq!(tree.to_string(), *source);
+            // Nodes cover the source without gaps or overlaps
+            let mut offset = 0;
+            for token in tree.root.tokens() {
+                assert_eq!(token.offset, offset);
+                offset += token.text.len();
+            }
+            assert_eq!(offset, source.len());
+        }
+    }
+
+    #[test]
+    fn item_nodes() {
+        let tree = SyntaxTree::parse("/// Class\npublic class C {\n  public func F() {}\n}\n\npublic class Broken extends {}\n");
+        let items: Vec<(NodeKind, String)> = tree.root.nodes().map(|node| (node.kind, node.to_string())).collect();
+
+        assert_eq!(items, vec![
+            (NodeKind::Class, "/// Class\npublic class C {\n  public func F() {}\n}".to_string()),
+            (NodeKind::Skipped, "public class Broken extends {}".to_string()),
+        ]);
+
+        let body = tree.root.nodes().next().unwrap().nodes().next().unwrap();
+        assert_eq!(body.kind, NodeKind::Block);
+        assert_eq!(body.nodes().map(|node| node.to_string()).collect::<Vec<_>>(), vec!["{}"]);
+    }
+
+    #[test]
+    fn lower_matches_recovering() {
+        let source = "module Mod\n/// Enum\nenum A { B = 0 }\n\npublic class Broken extends {}\n\nstatic public static func F();\n";
+        let (file, diagnostics) = SyntaxTree::parse(source).lower("test.reds");
+        let (expected_file, expected_diagnostics) = crate::parse_file_recovering("test.reds", source);
+
+        assert_eq!(format!("{:?}", file), format!("{:?}", expected_file));
+        assert_eq!(diagnostics, expected_diagnostics);
+    }
+
+    #[test]
+    fn lower_edited_tokens() {
+        let mut tree = SyntaxTree::parse("enum A { B = 0 }\n\nenum Long { C = 1 }\n");
+        // Renaming the first enum moves the second one in the text being lowered
+        if let Some(Element::Node(item)) = tree.root.children.first_mut() {
+            if let Some(Element::Token(token)) = item.children.get_mut(2) {
+                token.text = "Renamed";
+            }
+        }
+        let (file, diagnostics) = tree.lower("test.reds");
+
+        assert!(diagnostics.is_empty());
+        let names: Vec<(String, usize)> = file.defs.iter()
+            .filter_map(|def| match def {
+                AnyDefinition::Enum(def) => Some((def.name.to_string(), def.span.line)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, vec![("Renamed".to_string(), 1), ("Long".to_string(), 3)]);
+    }
+}
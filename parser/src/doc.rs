@@ -0,0 +1,680 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ast::{
+    AnyDefinition, ClassDefinition, CommentDefinition, EnumDefinition, EnumValDefinition, FieldDefinition,
+    FileDefinition, FuncDefinition, Ident, MemberDefinition, ModuleDefinition, Span
+};
+use crate::diagnostic::Diagnostic;
+use crate::visit::{walk_class, walk_definition, walk_enum, walk_file, Visitor};
+
+/// Scope a documentation comment applies to, as described in `docu-comment-spec.md`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DocScope {
+    File,
+    Module,
+    Enum,
+    Class,
+    Func,
+    Field,
+}
+
+/// Definition named by a block tag, which a detached comment documents wherever it appears
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DocTarget {
+    /// `@file`, always the file containing the comment
+    File,
+    Module(String),
+    Enum(String),
+    Class(String),
+    /// `@func Name` for a global function, or `@func Class.Name` for a method
+    Func(String),
+    /// `@field Name` for a global field, or `@field Class.Name` for a member
+    Field(String),
+}
+
+/// Inline tag within a documentation comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DocTag {
+    Author(String),
+    Copyright(String),
+    License(String),
+    Version(String),
+    Group(String),
+    Param(String, String),
+    Returns(String),
+    See(String),
+    /// Tag name (without the `@`) and its text
+    Unknown(String, String),
+}
+
+/// Named Markdown block started by a `@section` tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocSection {
+    pub name: String,
+    pub body: Vec<Arc<str>>,
+}
+
+/// Documentation comment split into its Markdown content and typed tags
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocComment {
+    /// Markdown lines preceding the first `@section`
+    pub body: Vec<Arc<str>>,
+    pub sections: Vec<DocSection>,
+    pub tags: Vec<DocTag>,
+    /// Target named by the first block tag, if any. [`attach_detached`] splits a detached comment
+    /// with more than one block tag into a comment per tag.
+    pub target: Option<DocTarget>,
+}
+
+impl DocTarget {
+    fn new(name: &str, text: &str) -> Option<Self> {
+        let (target, _) = split_word(text);
+        let target = target.to_string();
+        match name {
+            "file" => Some(DocTarget::File),
+            "module" => Some(DocTarget::Module(target)),
+            "enum" => Some(DocTarget::Enum(target)),
+            "class" => Some(DocTarget::Class(target)),
+            "func" => Some(DocTarget::Func(target)),
+            "field" => Some(DocTarget::Field(target)),
+            _ => None,
+        }
+    }
+
+    pub fn scope(&self) -> DocScope {
+        match self {
+            DocTarget::File => DocScope::File,
+            DocTarget::Module(_) => DocScope::Module,
+            DocTarget::Enum(_) => DocScope::Enum,
+            DocTarget::Class(_) => DocScope::Class,
+            DocTarget::Func(_) => DocScope::Func,
+            DocTarget::Field(_) => DocScope::Field,
+        }
+    }
+}
+
+impl fmt::Display for DocTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocTarget::File => write!(f, "file"),
+            DocTarget::Module(name) => write!(f, "module `{}`", name),
+            DocTarget::Enum(name) => write!(f, "enum `{}`", name),
+            DocTarget::Class(name) => write!(f, "class `{}`", name),
+            DocTarget::Func(name) => write!(f, "func `{}`", name),
+            DocTarget::Field(name) => write!(f, "field `{}`", name),
+        }
+    }
+}
+
+impl DocTag {
+    fn new(name: &str, text: String) -> Self {
+        match name {
+            "author" => DocTag::Author(text),
+            "copyright" => DocTag::Copyright(text),
+            "license" => DocTag::License(text),
+            "version" => DocTag::Version(text),
+            "group" => DocTag::Group(text),
+            "param" => {
+                let (param, description) = split_word(&text);
+                DocTag::Param(param.to_string(), description.to_string())
+            }
+            "returns" => DocTag::Returns(text),
+            "see" => DocTag::See(text),
+            _ => DocTag::Unknown(name.to_string(), text),
+        }
+    }
+
+    /// Tag name as written in the comment, without the `@`
+    pub fn name(&self) -> &str {
+        match self {
+            DocTag::Author(_) => "author",
+            DocTag::Copyright(_) => "copyright",
+            DocTag::License(_) => "license",
+            DocTag::Version(_) => "version",
+            DocTag::Group(_) => "group",
+            DocTag::Param(_, _) => "param",
+            DocTag::Returns(_) => "returns",
+            DocTag::See(_) => "see",
+            DocTag::Unknown(name, _) => name,
+        }
+    }
+
+    /// Whether the spec allows this tag within the given scope
+    pub fn allowed_in(&self, scope: DocScope) -> bool {
+        match self {
+            DocTag::Author(_) | DocTag::Copyright(_) | DocTag::License(_) | DocTag::Version(_) =>
+                matches!(scope, DocScope::File | DocScope::Module),
+            DocTag::Group(_) => matches!(scope, DocScope::Enum | DocScope::Class | DocScope::Func),
+            DocTag::Param(_, _) | DocTag::Returns(_) => scope == DocScope::Func,
+            DocTag::See(_) => true,
+            DocTag::Unknown(_, _) => false,
+        }
+    }
+
+    fn append(&mut self, line: &str) {
+        let text = match self {
+            DocTag::Author(text)
+            | DocTag::Copyright(text)
+            | DocTag::License(text)
+            | DocTag::Version(text)
+            | DocTag::Group(text)
+            | DocTag::Param(_, text)
+            | DocTag::Returns(text)
+            | DocTag::See(text)
+            | DocTag::Unknown(_, text) => text,
+        };
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(line);
+    }
+}
+
+/// Splits off the first whitespace-separated word of a string
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// Removes blank lines from the start and end of a block of lines
+pub(crate) fn trim_blank_lines(lines: &mut Vec<Arc<str>>) {
+    while matches!(lines.last(), Some(line) if line.trim().is_empty()) {
+        lines.pop();
+    }
+    let leading = lines.iter().take_while(|line| line.trim().is_empty()).count();
+    lines.drain(..leading);
+}
+
+impl DocComment {
+    /// Splits comment lines into Markdown and tags. A line starting with `@name` begins a tag,
+    /// and any following lines up to a blank line or the next tag continue its text. Block tags
+    /// such as `@class Name` set the target of the comment rather than adding a tag.
+    pub fn parse(lines: &[Arc<str>]) -> Self {
+        let mut doc = DocComment::default();
+        let mut in_tag = false;
+
+        for line in lines {
+            let trimmed = line.trim();
+            if let Some(tag) = trimmed.strip_prefix('@') {
+                let (name, text) = split_word(tag);
+                if name == "section" {
+                    doc.sections.push(DocSection { name: text.to_string(), body: Vec::new() });
+                    in_tag = false;
+                }
+                else if let Some(target) = DocTarget::new(name, text) {
+                    doc.target.get_or_insert(target);
+                    in_tag = false;
+                }
+                else {
+                    doc.tags.push(DocTag::new(name, text.to_string()));
+                    in_tag = true;
+                }
+            }
+            else if in_tag && !trimmed.is_empty() {
+                doc.tags.last_mut().unwrap().append(trimmed);
+            }
+            else {
+                in_tag = false;
+                match doc.sections.last_mut() {
+                    Some(section) => section.body.push(line.clone()),
+                    None => doc.body.push(line.clone()),
+                }
+            }
+        }
+
+        trim_blank_lines(&mut doc.body);
+        for section in &mut doc.sections {
+            trim_blank_lines(&mut section.body);
+        }
+
+        doc
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tags.iter().filter_map(|tag| match tag {
+            DocTag::Param(name, description) => Some((name.as_str(), description.as_str())),
+            _ => None,
+        })
+    }
+
+    pub fn returns(&self) -> Option<&str> {
+        self.tags.iter().find_map(|tag| match tag {
+            DocTag::Returns(description) => Some(description.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn see(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().filter_map(|tag| match tag {
+            DocTag::See(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.tags.iter().find_map(|tag| match tag {
+            DocTag::Group(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Reports every tag that the spec doesn't allow within the given scope
+    pub fn validate(&self, scope: DocScope, span: Span) -> Vec<Diagnostic> {
+        self.tags.iter()
+            .filter(|tag| !tag.allowed_in(scope))
+            .map(|tag| match tag {
+                DocTag::Unknown(name, _) => Diagnostic::warning(format!("unknown documentation tag `@{}`", name), span),
+                _ => Diagnostic::warning(format!("`@{}` isn't allowed in {:?} documentation", tag.name(), scope), span),
+            })
+            .collect()
+    }
+}
+
+fn validate_comments(comments: &Option<CommentDefinition>, scope: DocScope, diagnostics: &mut Vec<Diagnostic>) {
+    if let Some(comments) = comments {
+        diagnostics.extend(comments.doc.validate(scope, comments.span));
+    }
+}
+
+fn validate_func(func: &FuncDefinition, diagnostics: &mut Vec<Diagnostic>) {
+    validate_comments(&func.comments, DocScope::Func, diagnostics);
+
+    if let Some(comments) = &func.comments {
+        for (name, _) in comments.doc.params() {
+            if !func.params.iter().any(|param| param.name.as_str() == name) {
+                diagnostics.push(Diagnostic::warning(
+                    format!("`@param {}` doesn't match any parameter of `{}`", name, func.name.as_str()),
+                    comments.span
+                ));
+            }
+        }
+    }
+}
+
+/// Checks documentation against the scope of the definition it documents
+struct Validator(Vec<Diagnostic>);
+
+impl Visitor for Validator {
+    fn visit_file(&mut self, file: &FileDefinition) {
+        validate_comments(&file.comments, DocScope::File, &mut self.0);
+        walk_file(self, file);
+    }
+    fn visit_module(&mut self, module: &ModuleDefinition) {
+        validate_comments(&module.comments, DocScope::Module, &mut self.0);
+    }
+    fn visit_definition(&mut self, def: &AnyDefinition) {
+        match def {
+            // Free-standing comments are in the scope they name, or else the file scope
+            AnyDefinition::Comments(comments) => {
+                let scope = comments.doc.target.as_ref().map_or(DocScope::File, DocTarget::scope);
+                self.0.extend(comments.doc.validate(scope, comments.span));
+            }
+            _ => walk_definition(self, def),
+        }
+    }
+    fn visit_enum(&mut self, enum_: &EnumDefinition) {
+        validate_comments(&enum_.comments, DocScope::Enum, &mut self.0);
+        walk_enum(self, enum_);
+    }
+    fn visit_enum_value(&mut self, value: &EnumValDefinition) {
+        validate_comments(&value.comments, DocScope::Field, &mut self.0);
+    }
+    fn visit_class(&mut self, class: &ClassDefinition) {
+        validate_comments(&class.comments, DocScope::Class, &mut self.0);
+        walk_class(self, class);
+    }
+    fn visit_func(&mut self, func: &FuncDefinition) {
+        validate_func(func, &mut self.0);
+    }
+    fn visit_field(&mut self, field: &FieldDefinition) {
+        validate_comments(&field.comments, DocScope::Field, &mut self.0);
+    }
+}
+
+/// Checks the documentation of every definition in a file against the tags allowed in its scope
+pub fn validate_file(file: &FileDefinition) -> Vec<Diagnostic> {
+    let mut validator = Validator(Vec::new());
+    validator.visit_file(file);
+    validator.0
+}
+
+/// Whether a definition in a file with the given module is called `target`, either by its own
+/// name or qualified with the module
+fn is_named(module: Option<&str>, name: &Ident, target: &str) -> bool {
+    name.as_str() == target || module.is_some_and(|module| {
+        target.strip_prefix(module)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .is_some_and(|rest| rest == name.as_str())
+    })
+}
+
+/// Finds the documentation slot of the definition a block tag names, searching every file
+fn find_target<'a>(files: &'a mut [FileDefinition], target: &DocTarget) -> Option<&'a mut Option<CommentDefinition>> {
+    let full = match target {
+        DocTarget::Module(name) | DocTarget::Enum(name) | DocTarget::Class(name)
+        | DocTarget::Func(name) | DocTarget::Field(name) => name.as_str(),
+        DocTarget::File => return None,
+    };
+    // Members are named as `Class.member`, where the class may also be qualified
+    let (owner, member) = match full.rfind('.') {
+        Some(i) => (Some(&full[..i]), &full[i + 1..]),
+        None => (None, full),
+    };
+
+    if let DocTarget::Module(_) = target {
+        return files.iter_mut()
+            .filter_map(|file| file.module.as_mut())
+            .find(|header| header.name() == full)
+            .map(|header| &mut header.comments);
+    }
+
+    for file in files.iter_mut() {
+        let module_name = file.module.as_ref().map(ModuleDefinition::name);
+        let module = module_name.as_deref();
+        for def in file.defs.iter_mut() {
+            let slot = match def {
+                AnyDefinition::Enum(enum_) if matches!(target, DocTarget::Enum(_)) && is_named(module, &enum_.name, full) =>
+                    Some(&mut enum_.comments),
+                AnyDefinition::Func(func) if matches!(target, DocTarget::Func(_)) && is_named(module, &func.name, full) =>
+                    Some(&mut func.comments),
+                AnyDefinition::Field(field) if matches!(target, DocTarget::Field(_)) && is_named(module, &field.name, full) =>
+                    Some(&mut field.comments),
+                AnyDefinition::Class(class) => {
+                    if matches!(target, DocTarget::Class(_)) && is_named(module, &class.name, full) {
+                        Some(&mut class.comments)
+                    }
+                    else if owner.is_some_and(|owner| is_named(module, &class.name, owner)) {
+                        class.members.iter_mut().find_map(|m| match m {
+                            MemberDefinition::Function(func) if matches!(target, DocTarget::Func(_)) && func.name.as_str() == member =>
+                                Some(&mut func.comments),
+                            MemberDefinition::Field(field) if matches!(target, DocTarget::Field(_)) && field.name.as_str() == member =>
+                                Some(&mut field.comments),
+                            _ => None,
+                        })
+                    }
+                    else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            if slot.is_some() {
+                return slot;
+            }
+        }
+    }
+
+    None
+}
+
+/// Whether a comment line is a block tag, such as `@class Name`
+fn is_block_tag(line: &str) -> bool {
+    line.trim().strip_prefix('@').is_some_and(|tag| {
+        let (name, text) = split_word(tag);
+        DocTarget::new(name, text).is_some()
+    })
+}
+
+/// Splits a comment before each block tag after its first, so that each part names one target.
+/// Lines before the first block tag stay with it.
+fn split_targets(comments: CommentDefinition) -> Vec<CommentDefinition> {
+    let mut parts: Vec<Vec<Arc<str>>> = vec![Vec::new()];
+    let mut tagged = false;
+    for line in &comments.lines {
+        if is_block_tag(line) {
+            if tagged {
+                parts.push(Vec::new());
+            }
+            tagged = true;
+        }
+        parts.last_mut().unwrap().push(line.clone());
+    }
+
+    if parts.len() == 1 {
+        return vec![comments];
+    }
+    parts.into_iter().map(|lines| CommentDefinition::new(lines, comments.span)).collect()
+}
+
+/// Adds a detached comment to the documentation of a definition, after any it already has
+fn attach(slot: &mut Option<CommentDefinition>, comments: CommentDefinition) {
+    match slot {
+        Some(existing) => {
+            existing.lines.push(Arc::from(""));
+            existing.lines.extend(comments.lines);
+            existing.doc = DocComment::parse(&existing.lines);
+        }
+        None => *slot = Some(comments),
+    }
+}
+
+/// Moves every detached comment with a block tag onto the definition it names. `@file` comments
+/// document their own file, while other targets are looked up across all of the files, so scripts
+/// can be documented from separate files. Definitions may be named as written or qualified with
+/// their module, and `@module` comments document the `module` header of a file in that module.
+/// A comment with several block tags is split before each of them, and each part documents the
+/// target its tag names.
+///
+/// Returns a warning for each comment whose target couldn't be found, paired with the index of
+/// the file containing that comment.
+pub fn attach_detached(files: &mut [FileDefinition]) -> Vec<(usize, Diagnostic)> {
+    let mut diagnostics = Vec::new();
+
+    for file_index in 0..files.len() {
+        let defs = std::mem::take(&mut files[file_index].defs);
+        files[file_index].defs = defs.into_iter()
+            .flat_map(|def| match def {
+                AnyDefinition::Comments(comments) if comments.doc.target.is_some() =>
+                    split_targets(comments).into_iter().map(AnyDefinition::Comments).collect(),
+                def => vec![def],
+            })
+            .collect();
+
+        let mut def_index = 0;
+        while def_index < files[file_index].defs.len() {
+            let (target, span) = match &files[file_index].defs[def_index] {
+                AnyDefinition::Comments(CommentDefinition { doc: DocComment { target: Some(target), .. }, span, .. }) =>
+                    (target.clone(), *span),
+                _ => {
+                    def_index += 1;
+                    continue;
+                }
+            };
+
+            let found = match target {
+                DocTarget::File => true,
+                _ => find_target(files, &target).is_some(),
+            };
+            if !found {
+                diagnostics.push((file_index, Diagnostic::warning(
+                    format!("documentation names {}, which isn't defined", target),
+                    span
+                )));
+                def_index += 1;
+                continue;
+            }
+
+            let comments = match files[file_index].defs.remove(def_index) {
+                AnyDefinition::Comments(comments) => comments,
+                _ => unreachable!(),
+            };
+            match target {
+                DocTarget::File => attach(&mut files[file_index].comments, comments),
+                _ => attach(find_target(files, &target).unwrap(), comments),
+            }
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<Arc<str>> {
+        text.lines().map(Arc::from).collect()
+    }
+
+    #[test]
+    fn parse_tags() {
+        let doc = DocComment::parse(&lines("
+Adds two numbers
+
+Returns **zero** on overflow
+@param a The first number,
+  which may continue on the next line
+@param b The second number
+@returns The sum
+
+@section Examples
+`Add(1, 2)`
+@see OperatorAdd"));
+
+        assert_eq!(doc.body, lines("Adds two numbers\n\nReturns **zero** on overflow"));
+        assert_eq!(doc.sections, vec![DocSection { name: "Examples".to_string(), body: lines("`Add(1, 2)`") }]);
+        assert_eq!(doc.params().collect::<Vec<_>>(), vec![
+            ("a", "The first number, which may continue on the next line"),
+            ("b", "The second number"),
+        ]);
+        assert_eq!(doc.returns(), Some("The sum"));
+        assert_eq!(doc.see().collect::<Vec<_>>(), vec!["OperatorAdd"]);
+    }
+
+    #[test]
+    fn validate_scope() {
+        let doc = DocComment::parse(&lines("@author flib\n@group Movement\n@returns Nothing\n@frobnicate"));
+        let messages: Vec<String> = doc.validate(DocScope::Class, Span::default())
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+
+        assert_eq!(messages, vec![
+            "`@author` isn't allowed in Class documentation",
+            "`@returns` isn't allowed in Class documentation",
+            "unknown documentation tag `@frobnicate`",
+        ]);
+    }
+
+    #[test]
+    fn validate_params() {
+        let file = crate::parse_file("test.reds", "
+/// @param value The value
+/// @param missing Not a parameter
+public func Set(value: Int32) {}").unwrap();
+
+        let diagnostics = validate_file(&file);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "`@param missing` doesn't match any parameter of `Set`");
+    }
+
+    #[test]
+    fn parse_block_tags() {
+        let doc = DocComment::parse(&lines("@class PlayerPuppet\nThe player\n@see GameObject"));
+        assert_eq!(doc.target, Some(DocTarget::Class("PlayerPuppet".to_string())));
+        assert_eq!(doc.body, lines("The player"));
+        assert_eq!(doc.see().collect::<Vec<_>>(), vec!["GameObject"]);
+
+        let doc = DocComment::parse(&lines("@file\n@author flib"));
+        assert_eq!(doc.target, Some(DocTarget::File));
+        assert_eq!(doc.tags, vec![DocTag::Author("flib".to_string())]);
+    }
+
+    #[test]
+    fn attach_across_files() {
+        let mut files = vec![
+            crate::parse_file("game.reds", "
+/// @file
+/// @author flib
+public class Player {
+  public let health: Float;
+  public func Heal(amount: Float) {}
+}
+public func Respawn() {}").unwrap(),
+            crate::parse_file("docs.reds", "
+/// @class Player
+/// The player character
+
+/// @field Player.health
+/// Current health
+
+/// @func Player.Heal
+/// @param amount Health to restore
+/// @func Respawn
+/// Respawns at the last checkpoint
+
+/// @enum Missing
+").unwrap(),
+        ];
+
+        let diagnostics = attach_detached(&mut files);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, 1);
+        assert_eq!(diagnostics[0].1.message, "documentation names enum `Missing`, which isn't defined");
+
+        let file_doc = &files[0].comments.as_ref().unwrap().doc;
+        assert_eq!(file_doc.tags, vec![DocTag::Author("flib".to_string())]);
+
+        let (class, respawn) = match &files[0].defs[..] {
+            [AnyDefinition::Class(class), AnyDefinition::Func(respawn)] => (class, respawn),
+            defs => panic!("unexpected definitions {:?}", defs),
+        };
+        assert_eq!(class.comments.as_ref().unwrap().doc.body, lines("The player character"));
+        match &class.members[..] {
+            [MemberDefinition::Field(field), MemberDefinition::Function(func)] => {
+                assert_eq!(field.comments.as_ref().unwrap().doc.body, lines("Current health"));
+                // A comment with two block tags documents both targets
+                let doc = &func.comments.as_ref().unwrap().doc;
+                assert_eq!(doc.params().collect::<Vec<_>>(), vec![("amount", "Health to restore")]);
+                assert!(doc.body.is_empty());
+            }
+            members => panic!("unexpected members {:?}", members),
+        }
+        assert_eq!(respawn.comments.as_ref().unwrap().doc.body, lines("Respawns at the last checkpoint"));
+
+        // Only the unresolved comment is left behind
+        assert_eq!(files[1].defs.len(), 1);
+    }
+
+    #[test]
+    fn attach_qualified() {
+        let mut files = vec![
+            crate::parse_file("mod.reds", "module My.Mod
+public func Run();
+public class Tool { public func Use(); }").unwrap(),
+            crate::parse_file("docs.reds", "
+/// @module My.Mod
+/// A mod
+
+/// @func My.Mod.Run
+/// Runs the mod
+
+/// @func My.Mod.Tool.Use
+/// Uses the tool
+").unwrap(),
+        ];
+
+        assert!(attach_detached(&mut files).is_empty());
+        assert!(files[1].defs.is_empty());
+        assert_eq!(files[0].module.as_ref().unwrap().comments.as_ref().unwrap().doc.body, lines("A mod"));
+        match &files[0].defs[..] {
+            [AnyDefinition::Func(run), AnyDefinition::Class(tool)] => {
+                assert_eq!(run.comments.as_ref().unwrap().doc.body, lines("Runs the mod"));
+                match &tool.members[..] {
+                    [MemberDefinition::Function(func)] => assert_eq!(func.comments.as_ref().unwrap().doc.body, lines("Uses the tool")),
+                    members => panic!("unexpected members {:?}", members),
+                }
+            }
+            defs => panic!("unexpected definitions {:?}", defs),
+        }
+    }
+}
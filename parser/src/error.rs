@@ -0,0 +1,50 @@
+use std::error;
+use std::fmt;
+
+use peg::str::LineCol;
+
+/// Error returned when the input can't be parsed, located at the furthest point the parser reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number of the error
+    pub line: usize,
+    /// 1-based column number of the error
+    pub column: usize,
+    /// Byte offset of the error from the start of the input
+    pub offset: usize,
+    /// Sorted list of the tokens or rules that would have allowed parsing to continue
+    pub expected: Vec<&'static str>,
+}
+
+impl From<peg::error::ParseError<LineCol>> for ParseError {
+    fn from(err: peg::error::ParseError<LineCol>) -> Self {
+        let mut expected: Vec<&'static str> = err.expected.tokens().collect();
+        expected.sort_unstable();
+
+        ParseError {
+            line: err.location.line,
+            column: err.location.column,
+            offset: err.location.offset,
+            expected,
+        }
+    }
+}
+
+impl ParseError {
+    /// Description of the error without its location
+    pub fn message(&self) -> String {
+        match self.expected.as_slice() {
+            [] => "expected <unreported>".to_string(),
+            [single] => format!("expected {}", single),
+            many => format!("expected one of {}", many.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message())
+    }
+}
+
+impl error::Error for ParseError {}
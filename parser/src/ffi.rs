@@ -0,0 +1,131 @@
+//! C API of the parser library, for tools written in other languages, which is built with the
+//! `capi` feature. Definitions are returned as JSON documents, which the caller releases with
+//! [`redscript_free`]. The C header is generated into `include/redscript_parser.h` with
+//! `cbindgen --config cbindgen.toml --output include/redscript_parser.h` from the crate directory.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic;
+use std::ptr;
+use std::slice;
+
+use crate::ast::Ident;
+use crate::parse_file_recovering;
+use crate::source::decode;
+
+/// Parses a script file into a JSON document of its definitions and diagnostics, as
+/// `{"file": ..., "diagnostics": [...]}`, or `{"error": "..."}` if the source can't be decoded
+pub fn parse_json(name: &str, bytes: &[u8]) -> String {
+    let value = match decode(bytes) {
+        Ok(source) => {
+            let (mut file, mut diagnostics) = parse_file_recovering(name, &source.text);
+            source.map_spans(&mut file, &mut diagnostics);
+            serde_json::json!({ "file": file, "diagnostics": diagnostics })
+        }
+        Err(err) => serde_json::json!({ "error": err.to_string() }),
+    };
+    value.to_string()
+}
+
+/// Parses a buffer holding a script file, which may be UTF-8, or UTF-16 with a byte order mark.
+/// Definitions are named as belonging to the file `name`, which may be null.
+///
+/// Returns a NUL-terminated JSON document of the definitions and diagnostics,
+/// `{"file": ..., "diagnostics": [...]}`, or `{"error": "..."}` if the buffer can't be decoded.
+/// Returns null if `name` isn't UTF-8 or parsing failed unexpectedly. The result must be released
+/// with `redscript_free`.
+///
+/// # Safety
+///
+/// `source` must point to `len` readable bytes, or may be null if `len` is 0, and `name` must be
+/// null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn redscript_parse(name: *const c_char, source: *const u8, len: usize) -> *mut c_char {
+    let name = if name.is_null() {
+        ""
+    }
+    else {
+        match CStr::from_ptr(name).to_str() {
+            Ok(name) => name,
+            Err(_) => return ptr::null_mut(),
+        }
+    };
+    let bytes = match (source.is_null(), len) {
+        (true, 0) => &[][..],
+        (true, _) => return ptr::null_mut(),
+        (false, _) => slice::from_raw_parts(source, len),
+    };
+
+    // Unwinding into the caller's frames would be undefined behaviour
+    panic::catch_unwind(|| parse_json(name, bytes))
+        .ok()
+        .and_then(|json| CString::new(json).ok())
+        .map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Releases a result returned by `redscript_parse`. Does nothing if `json` is null.
+///
+/// # Safety
+///
+/// `json` must be null, or a result of `redscript_parse` that hasn't already been released.
+#[no_mangle]
+pub unsafe extern "C" fn redscript_free(json: *mut c_char) {
+    if !json.is_null() {
+        drop(CString::from_raw(json));
+    }
+}
+
+/// Releases the identifier names kept from earlier calls to `redscript_parse`. Names are shared
+/// between parses, so a host that parses many unrelated scripts should call this now and then,
+/// such as once it's done with a batch of them.
+#[no_mangle]
+pub extern "C" fn redscript_release_names() {
+    Ident::release_unused();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls the C API as a foreign caller would, and reads back its result
+    fn parse(name: Option<&str>, source: &[u8]) -> serde_json::Value {
+        let name = name.map(|name| CString::new(name).unwrap());
+        unsafe {
+            let json = redscript_parse(name.as_ref().map_or(ptr::null(), |name| name.as_ptr()), source.as_ptr(), source.len());
+            assert!(!json.is_null());
+            let value = serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            redscript_free(json);
+            value
+        }
+    }
+
+    #[test]
+    fn parse_buffer() {
+        let value = parse(Some("test.reds"), b"/// Doc\nenum A { B = 1 }\nclass {");
+
+        assert_eq!(value["file"]["name"], "test.reds");
+        assert_eq!(value["file"]["defs"][0]["Enum"]["values"][0]["value"], 1);
+        assert_eq!(value["diagnostics"][0]["severity"], "Error");
+        assert_eq!(value["diagnostics"][0]["span"]["line"], 3);
+    }
+
+    #[test]
+    fn parse_buffer_invalid() {
+        let value = parse(None, b"\xFF\xFEA");
+        assert_eq!(value["error"], "invalid UTF-16 at byte 2");
+
+        unsafe {
+            assert!(redscript_parse(ptr::null(), ptr::null(), 1).is_null());
+            redscript_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn release_names() {
+        parse(Some("test.reds"), b"class ReleasedName {}");
+        redscript_release_names();
+
+        let value = parse(Some("test.reds"), b"class ReleasedName {}");
+        assert_eq!(value["file"]["defs"][0]["Class"]["name"], "ReleasedName");
+    }
+}
@@ -1,23 +1,291 @@
-use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Range;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::convert::From;
 
-use peg::error::ParseError;
+use peg::RuleResult;
 
-use crate::ast::{AnyDefinition, Ident, ScalarType, ContainerType, VariableType, TypeDeclaration};
-use crate::ast::{Annotation, Visibility, ClassQualifier, FuncQualifier, ParamQualifier, FieldQualifier};
+use crate::ast::{AnyDefinition, Ident, Span, ScalarType, ContainerType, VariableType, TypeDeclaration};
+use crate::ast::{Annotation, AnnotationArg, AnnotationKind, Visibility, ClassQualifier, FuncQualifier, ParamQualifier, FieldQualifier};
 use crate::ast::{CommentDefinition, EnumDefinition, EnumValDefinition, ClassDefinition};
 use crate::ast::{MemberDefinition, FuncDefinition, ParamDefinition, FieldDefinition, FileDefinition};
+use crate::ast::{ModuleDefinition, ImportDefinition, ImportKind};
+use crate::ast::{Statement, StatementKind, SwitchCase, Expression, ExpressionKind, Constant, StringKind, UnaryOp, BinaryOp};
+use crate::source::LineIndex;
+use crate::visit::{walk_func_mut, VisitorMut};
 
 pub mod ast;
+pub mod check;
+pub mod cst;
+pub mod diagnostic;
+pub mod doc;
+pub mod error;
+#[cfg(feature = "capi")]
+pub mod ffi;
+pub mod print;
+pub mod source;
+pub mod visit;
+#[cfg(all(target_arch = "wasm32", feature = "capi"))]
+pub mod wasm;
 
-fn VecToStringTrimmed(v: Vec<&str>) -> String {
-    String::from(v.concat().trim_end())
+pub use crate::diagnostic::Diagnostic;
+pub use crate::error::ParseError;
+
+/// Parses a whole script file into its top-level definitions, in source order. The `name` is only
+/// used to identify the file, and is typically its path. The source may start with a byte order
+/// mark and use either LF or CRLF line endings; see [`source::decode`] for reading files that
+/// aren't UTF-8. Warnings, such as those about repeated modifiers, aren't returned, but
+/// [`parse_file_recovering`] returns them along with the definitions.
+pub fn parse_file(name: &str, source: &str) -> Result<FileDefinition, ParseError> {
+    let index = LineIndex::new(source);
+    redscript::file(source, &index, &RefCell::default(), name).map_err(|err| {
+        // Located by the index, which doesn't count a byte order mark as a column
+        let mut err = ParseError::from(err);
+        err.column = index.line_col(err.offset).1;
+        err
+    })
+}
+
+/// Parses a whole script file, skipping over any top-level item that can't be parsed rather than
+/// failing. Returns every definition that was parsed, along with a diagnostic for each skipped item
+/// located at the point parsing of that item failed, and for any problems found by [`check`] or
+/// misplaced documentation tags.
+pub fn parse_file_recovering(name: &str, source: &str) -> (FileDefinition, Vec<Diagnostic>) {
+    let index = LineIndex::new(source);
+    let reported = RefCell::default();
+    // The rule can always fall back to skipping, so only a bug in the grammar would make it fail
+    let items = redscript::file_items(source, &index, &reported).expect("file items should always parse");
+
+    let mut defs = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut skipped = Vec::new();
+    for (start, end, def) in items {
+        match def {
+            Some(def) => defs.push(def),
+            None => {
+                diagnostics.push(skipped_item(source, &index, start, end));
+                skipped.push(start..end);
+            }
+        }
+    }
+
+    finish_file(name, defs, diagnostics, reported, &skipped)
+}
+
+/// Diagnostic for a top-level item that couldn't be parsed, located where parsing it failed
+fn skipped_item(source: &str, index: &LineIndex, start: usize, end: usize) -> Diagnostic {
+    // Parse the skipped item again on its own, to find out why it failed
+    let text = &source[start..end];
+    let (offset, message) = match redscript::definition(text, &LineIndex::new(text), &RefCell::default()) {
+        Err(err) => (start + err.location.offset, format!("skipped item, {}", ParseError::from(err).message())),
+        Ok(_) => (start, "skipped item".to_string()),
+    };
+    Diagnostic::error(message, index.span(offset, end))
+}
+
+/// Creates a file from the definitions parsed from it, and adds any diagnostics reported while
+/// parsing, other than those within the `skipped` ranges, along with those of the checks on the
+/// finished file
+fn finish_file(
+    name: &str,
+    defs: Vec<AnyDefinition>,
+    mut diagnostics: Vec<Diagnostic>,
+    reported: RefCell<Vec<Diagnostic>>,
+    skipped: &[Range<usize>]
+) -> (FileDefinition, Vec<Diagnostic>) {
+    // Anything reported while parsing an item that was later skipped no longer applies
+    reported.borrow_mut().retain(|diagnostic| !skipped.iter().any(|range| range.contains(&diagnostic.span.start)));
+
+    let file = new_file(name, defs, &reported);
+    diagnostics.extend(reported.into_inner());
+    diagnostics.extend(check::check_file(&file));
+    diagnostics.extend(doc::validate_file(&file));
+
+    (file, diagnostics)
+}
+
+/// Parses a single `enum` declaration
+pub fn parse_enum(source: &str) -> Result<EnumDefinition, ParseError> {
+    Ok(redscript::enum_(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a single `class` or `struct` declaration, including its members. As with
+/// [`parse_file`], warnings about modifiers are dropped.
+pub fn parse_class(source: &str) -> Result<ClassDefinition, ParseError> {
+    Ok(redscript::class(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a single function declaration, with either a body or a terminating `;`. Warnings about
+/// its modifiers are dropped.
+pub fn parse_func(source: &str) -> Result<FuncDefinition, ParseError> {
+    Ok(redscript::func(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a single `let` field declaration, dropping any warnings about its modifiers
+pub fn parse_field(source: &str) -> Result<FieldDefinition, ParseError> {
+    Ok(redscript::field(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a type, such as `Int32` or `array<wref<GameObject>>`
+pub fn parse_type(source: &str) -> Result<TypeDeclaration, ParseError> {
+    Ok(redscript::type_(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a documentation comment block, or a run of single-line documentation comments
+pub fn parse_comments(source: &str) -> Result<CommentDefinition, ParseError> {
+    Ok(redscript::comments(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a single statement of a function body, such as `let x = Foo.Bar(1);` or an `if` with its
+/// blocks
+pub fn parse_statement(source: &str) -> Result<Statement, ParseError> {
+    Ok(redscript::statement(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses a single expression, such as `this.m_items[0] as Item`
+pub fn parse_expression(source: &str) -> Result<Expression, ParseError> {
+    Ok(redscript::expression(source, &LineIndex::new(source), &RefCell::default())?)
+}
+
+/// Parses the body of every function in a file parsed from `source`, which are otherwise skipped.
+/// A function whose body can't be parsed is left without one, and a warning is returned for it
+/// located where parsing failed.
+pub fn parse_bodies(file: &mut FileDefinition, source: &str) -> Vec<Diagnostic> {
+    let mut parser = BodyParser { source, index: LineIndex::new(source), diagnostics: Vec::new() };
+    parser.visit_file_mut(file);
+    parser.diagnostics
+}
+
+struct BodyParser<'a> {
+    source: &'a str,
+    index: LineIndex<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl VisitorMut for BodyParser<'_> {
+    fn visit_func_mut(&mut self, func: &mut FuncDefinition) {
+        if let Some(span) = func.body_span {
+            match redscript::body_at(self.source, &self.index, &RefCell::default(), &(span.start..span.end)) {
+                Ok(body) => func.body = Some(body),
+                Err(err) => {
+                    let offset = err.location.offset;
+                    let message = format!("body of `{}` not parsed, {}", func.name, ParseError::from(err).message());
+                    self.diagnostics.push(Diagnostic::warning(message, self.index.span(offset, span.end)));
+                }
+            }
+        }
+        walk_func_mut(self, func);
+    }
+}
+
+/// Reports a diagnostic found while parsing. Rules may be attempted more than once at the same
+/// position, so a diagnostic that was already reported is ignored.
+fn report(diagnostics: &RefCell<Vec<Diagnostic>>, diagnostic: Diagnostic) {
+    let mut diagnostics = diagnostics.borrow_mut();
+    if !diagnostics.iter().any(|d| d.span == diagnostic.span && d.message == diagnostic.message) {
+        diagnostics.push(diagnostic);
+    }
+}
+
+/// Creates a file from its top-level definitions, moving its `module` header out of them. A file
+/// may only belong to one module, so any further headers are reported and dropped.
+fn new_file(name: &str, defs: Vec<AnyDefinition>, diagnostics: &RefCell<Vec<Diagnostic>>) -> FileDefinition {
+    let mut module: Option<ModuleDefinition> = None;
+    let mut rest = Vec::with_capacity(defs.len());
+    for def in defs {
+        match def {
+            AnyDefinition::Module(header) => match &module {
+                Some(existing) => report(diagnostics, Diagnostic::warning(
+                    format!("file already belongs to module `{}`", existing.name()),
+                    header.span
+                )),
+                None => module = Some(header),
+            },
+            def => rest.push(def),
+        }
+    }
+
+    FileDefinition { name: Ident::new(name), comments: None, module, defs: rest }
+}
+
+/// Visibility or qualifier keyword of a definition, as they may be written in any order
+enum Modifier<Q> {
+    Visibility(Visibility),
+    Qualifier(Q),
+}
+
+/// Splits modifiers into the visibility, if one was written, and the qualifiers in the order
+/// written. A repeated modifier or a second visibility is reported and dropped, while qualifiers
+/// that contradict each other are kept as written for [`check`] to report.
+fn split_modifiers<Q: Copy + PartialEq + fmt::Display>(
+    modifiers: Vec<(Modifier<Q>, Span)>,
+    diagnostics: &RefCell<Vec<Diagnostic>>
+) -> (Option<Visibility>, Vec<Q>) {
+    let mut visibility = None;
+    let mut qualifiers = Vec::new();
+
+    for (modifier, span) in modifiers {
+        match modifier {
+            Modifier::Visibility(v) => match visibility {
+                Some(existing) if existing == v =>
+                    report(diagnostics, Diagnostic::warning(format!("duplicate `{}` visibility", v), span)),
+                Some(existing) =>
+                    report(diagnostics, Diagnostic::warning(format!("`{}` contradicts earlier `{}` visibility", v, existing), span)),
+                None => visibility = Some(v),
+            },
+            Modifier::Qualifier(q) if qualifiers.contains(&q) =>
+                report(diagnostics, Diagnostic::warning(format!("duplicate `{}` qualifier", q), span)),
+            Modifier::Qualifier(q) => qualifiers.push(q),
+        }
+    }
+
+    (visibility, qualifiers)
+}
+
+/// Input methods that move a rule straight to another position, so a single item of a file can be
+/// parsed in place. Only forward moves to a character boundary match.
+trait SkipTo {
+    fn skip_to(&self, pos: usize, target: usize) -> RuleResult<()>;
+    fn skip_to_end(&self, pos: usize) -> RuleResult<()>;
+}
+
+impl SkipTo for str {
+    fn skip_to(&self, pos: usize, target: usize) -> RuleResult<()> {
+        if pos <= target && self.is_char_boundary(target) {
+            RuleResult::Matched(target, ())
+        }
+        else {
+            RuleResult::Failed
+        }
+    }
+
+    fn skip_to_end(&self, pos: usize) -> RuleResult<()> {
+        self.skip_to(pos, self.len())
+    }
+}
+
+/// Folds the operands of a level of left-associative binary operators, starting at `start`, into
+/// a single expression
+fn binary(index: &LineIndex, start: usize, x: Expression, rest: Vec<(BinaryOp, Expression, usize)>) -> Expression {
+    rest.into_iter().fold(x, |x, (op, y, end)| Expression {
+        kind: ExpressionKind::Binary(op, Box::new(x), Box::new(y)),
+        span: index.span(start, end),
+    })
+}
+
+/// Operator following an expression in the grammar, applied to it once the whole chain is parsed
+enum Postfix {
+    Member(Ident),
+    Index(Expression),
+    Call(Vec<Expression>),
 }
 
 peg::parser! {
-    grammar redscript() for str {
+    grammar redscript(index: &LineIndex, diagnostics: &RefCell<Vec<Diagnostic>>) for str {
         use peg::ParseLiteral;
+        use super::SkipTo;
 
         rule traced<T>(e: rule<T>) -> T =
             &(input:$([_]*) {
@@ -30,13 +298,25 @@ peg::parser! {
                 e.ok_or("")
             }
 
-        // Any amount of whitespace
-        rule _() = quiet!{ ([' ' | '\n' | '\r' | '\t'])* }
+        // Any amount of whitespace and ordinary (non-documentation) comments
+        rule _() = quiet!{ (ws_char() / plain_line_comment() / plain_block_comment())* }
+        // Any amount of whitespace only, for use within documentation comments
+        rule ws() = quiet!{ ws_char()* }
+        rule ws_char() = [' ' | '\n' | '\r' | '\t']
+        // `//` but not `///`, and `/*` but not `/**`, although the empty `/**/` is ordinary, and
+        // so are separator lines of four or more slashes
+        rule plain_line_comment() = "//" !doc_line_start() (!endl() [_])*
+        rule doc_line_start() = "/" !"/"
+        rule plain_block_comment() = "/*" !("*" !"/") (!"*/" [_])* "*/"
         rule space_sep<T>(r: rule<T>) -> Vec<T> = v:(r() ** _)          { v }
         rule comma_sep<T>(r: rule<T>) -> Vec<T> = v:(r() ** (_ "," _))  { v }
         rule dot_sep<T>(r: rule<T>) -> Vec<T> = v:(r() ** (_ "." _))    { v }
-        // Line end
-        rule endl() = "\n"
+        // Line end, either LF or CRLF
+        rule endl() = "\r"? "\n"
+        // UTF-8 byte order mark, which some editors write at the start of a file
+        rule bom() = "\u{FEFF}"
+        // Whitespace within a single line
+        rule inline_ws() = quiet!{ [' ' | '\r' | '\t']* }
 
         // Documentation comments
         rule block_comment_start() = "/**"
@@ -46,32 +326,46 @@ peg::parser! {
         //  - A single asterisk,
         //  - At least one space character
         // Those characters will be stripped from the comment before being output.
-        rule block_comment_line() -> Rc<String>
-            = (_ "* ")? s:$(!block_comment_end() !endl() [_])*
-            { Rc::new(VecToStringTrimmed(s)) }
+        rule block_comment_line() -> Arc<str>
+            = (ws() "* ")? s:$((!block_comment_end() !endl() [_])*)
+            { Arc::from(s.trim_end()) }
 
-        rule block_comment() -> CommentDefinition
-            = block_comment_start() _ lines:(block_comment_line() ** endl()) _ block_comment_end()
-            { CommentDefinition{ lines } }
+        rule block_comment() -> Vec<Arc<str>>
+            = block_comment_start() ws() lines:(block_comment_line() ** endl()) ws() block_comment_end()
+            { lines }
 
         // For each single-line comment, if there is one or more space characters between the three
         // slashes `///` and any number of non-space characters, the first of those whitespace
         // characters will be trimmed from the output.
-        rule line_comment() -> Rc<String>
-            = _ "///" [' ']? s:$(!endl() [_])* endl() { Rc::new(VecToStringTrimmed(s)) }
+        rule line_comment() -> Arc<str>
+            = inline_ws() "//" doc_line_start() [' ']? s:$((!endl() [_])*) { Arc::from(s.trim_end()) }
 
+        // Line comments continue only onto the directly following line. Either comment style
+        // consumes the rest of its final line, so a blank line directly after a comment can be
+        // detected the same way for both.
         pub rule comments() -> CommentDefinition
-            = block:block_comment() { block }
-            / lines:line_comment()+ { CommentDefinition{ lines } }
+            = start:position!() lines:(block_comment() / line_comment() ++ endl()) end:position!()
+            inline_ws() endl()?
+            { CommentDefinition::new(lines, index.span(start, end)) }
+
+        // A comment followed by a blank line or the end of input doesn't document what follows it,
+        // and neither does one that names its own target with a block tag
+        rule detached_comments() -> CommentDefinition
+            = c:comments() &(inline_ws() (endl() / ![_])) { c }
+            / c:comments() {? if c.doc.target.is_some() { Ok(c) } else { Err("detached comment") } }
 
 
 
-        // Recursive collection of curly-braced scope blocks to be ignored (ie. function body)
+        // Recursive collection of curly-braced scope blocks to be ignored (ie. function body).
+        // String literals and comments are skipped whole so any braces within them don't
+        // unbalance the block.
         rule scope_begin()   = "{"
-        rule scope_content() = quiet!{ (!['}'] [_])* }
+        rule scope_string()  = "\"" ("\\" [_] / !"\"" [_])* "\""
+        rule scope_comment() = "//" (!endl() [_])* / "/*" (!"*/" [_])* "*/"
+        rule scope_content() = quiet!{ (scope_string() / scope_comment() / !['{' | '}' | '"'] [_])* }
         rule scope_end()     = "}"
         rule scope_block()
-            = scope_begin() scope_content() scope_block()* scope_content() scope_end()
+            = scope_begin() scope_content() (scope_block() scope_content())* scope_end()
 
         // parses a keyword string but makes sure it's not part of an identifier
         rule keyword(id: &'static str) -> () =
@@ -79,20 +373,55 @@ peg::parser! {
 
         // redscript uses C-style identifiers
         rule ident() -> Ident
-            = x:$(['a'..='z' | 'A'..='Z' | '_']) xs:$(['0'..='9' | 'a'..='z' | 'A'..='Z' | '_']*)
-            { Ident::new(format!("{}{}", x, xs)) }
+            = quiet!{
+                id:$(['a'..='z' | 'A'..='Z' | '_'] ['0'..='9' | 'a'..='z' | 'A'..='Z' | '_']*)
+                { Ident::new(id) }
+            }
             / expected!("Valid identifier")
+
+        // Identifier along with the span it was parsed from
+        rule spanned_ident() -> (Ident, Span)
+            = start:position!() id:ident() end:position!() { (id, index.span(start, end)) }
        
 
-        rule anno_type() -> Ident
-            = "(" _ c:ident() _ ")" { c }
+        // String contents are kept as written, escape sequences included
+        rule string_literal() -> Arc<str>
+            = "\"" s:$(("\\" [_] / !"\"" [_])*) "\"" { Arc::from(s) }
+
+        rule anno_arg() -> AnnotationArg
+            = "n" s:string_literal()    { AnnotationArg::Name(s) }
+            / s:string_literal()        { AnnotationArg::String(s) }
+            / n:$("-"? number())        { AnnotationArg::Number(Arc::from(n)) }
+            / keyword("true")           { AnnotationArg::Bool(true) }
+            / keyword("false")          { AnnotationArg::Bool(false) }
+            / "!" _ a:anno_arg()        { AnnotationArg::Not(Box::new(a)) }
+            / id:ident() _ "(" _ args:comma_sep(<anno_arg()>) _ ")"
+                                        { AnnotationArg::Call(id, args) }
+            / id:ident()                { AnnotationArg::Ident(id) }
+
+        // Any arguments up to the closing parenthesis, with nested parentheses balanced
+        rule anno_raw_content() = (scope_string() / "(" anno_raw_content() ")" / !['(' | ')' | '"'] [_])*
+        rule anno_raw() -> AnnotationArg
+            = s:$(anno_raw_content()) { AnnotationArg::Raw(Arc::from(s.trim())) }
+
+        rule anno_type() -> (Ident, Span)
+            = "(" _ c:spanned_ident() _ ")" { c }
+        // Kind of an annotation, along with the span of its identifier if it has one
+        rule anno_kind() -> (AnnotationKind, Option<Span>)
+            = keyword("@replaceGlobal") _ "(" _ ")"     { (AnnotationKind::ReplaceGlobal, None) }
+            / keyword("@replaceMethod") _ t:anno_type() { (AnnotationKind::ReplaceMethod(t.0), Some(t.1)) }
+            / keyword("@addMethod") _ t:anno_type()     { (AnnotationKind::AddMethod(t.0), Some(t.1)) }
+            / keyword("@addField") _ t:anno_type()      { (AnnotationKind::AddField(t.0), Some(t.1)) }
+            / keyword("@wrapMethod") _ t:anno_type()    { (AnnotationKind::WrapMethod(t.0), Some(t.1)) }
+            / keyword("@if") _ "(" _ c:anno_arg() _ ")" { (AnnotationKind::If(c), None) }
+            / keyword("@if") _ "(" c:anno_raw() ")"     { (AnnotationKind::If(c), None) }
+            / "@" id:spanned_ident() _ "(" _ args:comma_sep(<anno_arg()>) _ ")"
+                { (AnnotationKind::Unsupported(id.0, args), Some(id.1)) }
+            / "@" id:spanned_ident() _ "(" raw:anno_raw() ")"
+                { (AnnotationKind::Unsupported(id.0, vec![raw]), Some(id.1)) }
         rule annotation() -> Annotation
-            = keyword("@replaceGlobal") _ "(" _ ")"     { Annotation::ReplaceGlobal }
-            / keyword("@replaceMethod") _ t:anno_type() { Annotation::ReplaceMethod(t) }
-            / keyword("@addMethod") _ t:anno_type()     { Annotation::AddMethod(t) }
-            / keyword("@addField") _ t:anno_type()      { Annotation::AddField(t) }
-            / "@" id:ident() _ "(" params:$(![')'] [_])* ")"
-                { Annotation::Unsupported(id, Rc::new(params.concat())) }
+            = start:position!() k:anno_kind() end:position!()
+                { Annotation { kind: k.0, ident_span: k.1, span: index.span(start, end) } }
 
         
         rule visibility() -> Visibility
@@ -102,6 +431,7 @@ peg::parser! {
         
         rule class_qualifier() -> ClassQualifier
             = keyword("abstract")   { ClassQualifier::Abstract }
+            / keyword("importonly") { ClassQualifier::ImportOnly }
             / keyword("struct")     { ClassQualifier::Struct }
             / keyword("native")     { ClassQualifier::Native }
 
@@ -111,8 +441,11 @@ peg::parser! {
             / keyword("const")      { FuncQualifier::Const }
             / keyword("exec")       { FuncQualifier::Exec }
             / keyword("final")      { FuncQualifier::Final }
+            / keyword("importonly") { FuncQualifier::ImportOnly }
             / keyword("native")     { FuncQualifier::Native }
+            / keyword("quest")      { FuncQualifier::Quest }
             / keyword("static")     { FuncQualifier::Static }
+            / keyword("unsafe")     { FuncQualifier::Unsafe }
 
         rule param_qualifier() -> ParamQualifier
             = keyword("out")        { ParamQualifier::Out }
@@ -123,16 +456,27 @@ peg::parser! {
             / keyword("edit")       { FieldQualifier::Edit }
             / keyword("final")      { FieldQualifier::Final }
             / keyword("native")     { FieldQualifier::Native }
-            / keyword("persistent") { FieldQualifier::Native }
+            / keyword("persistent") { FieldQualifier::Persistent }
             / keyword("static")     { FieldQualifier::Static }
 
+        // Visibility and qualifiers may be mixed in any order, and are checked once the whole
+        // definition has parsed
+        rule modifier<Q>(qualifier: rule<Q>) -> (Modifier<Q>, Span)
+            = start:position!()
+            m:(v:visibility() { Modifier::Visibility(v) } / q:qualifier() { Modifier::Qualifier(q) })
+            end:position!()
+            { (m, index.span(start, end)) }
 
         pub rule scalar_type() -> ScalarType
             = keyword("Void")       { ScalarType::Void }
             / keyword("Variant")    { ScalarType::Variant }
             / keyword("Bool")       { ScalarType::Bool }
+            / keyword("Int8")       { ScalarType::I8 }
+            / keyword("Int16")      { ScalarType::I16 }
             / keyword("Int32")      { ScalarType::I32 }
             / keyword("Int64")      { ScalarType::I64 }
+            / keyword("Uint8")      { ScalarType::U8 }
+            / keyword("Uint16")     { ScalarType::U16 }
             / keyword("Uint32")     { ScalarType::U32 }
             / keyword("Uint64")     { ScalarType::U64 }
             / keyword("Float")      { ScalarType::F32 }
@@ -141,24 +485,43 @@ peg::parser! {
             / keyword("CName")      { ScalarType::CName }
             / keyword("ResRef")     { ScalarType::Resource }
             / keyword("TweakDBID")  { ScalarType::TweakDBID }
+            / keyword("LocalizationString") { ScalarType::LocalizationString }
+            / keyword("NodeRef")    { ScalarType::NodeRef }
+            / keyword("CRUID")      { ScalarType::CRUID }
 
 
         pub rule type_args() -> TypeDeclaration
             = "<" _ t:type_() _ ">" { t }
 
+        rule array_size() -> usize
+            = quiet!{ n:$(['0'..='9']+) {? n.parse().or(Err("Array size")) } } / expected!("Array size")
+
         pub rule type_() -> TypeDeclaration
+            = start:position!() t:type_kind() end:position!()
+                { TypeDeclaration { span: Some(index.span(start, end)), ..t } }
+        rule type_kind() -> TypeDeclaration
             = scal:scalar_type()
                 { TypeDeclaration::leaf(VariableType::Scalar(scal)) }
-            / comp:ident()
-                { TypeDeclaration::leaf(VariableType::Compound(comp)) }
-            / keyword("array") targ:type_args()
+            / keyword("array") _ targ:type_args()
                 { TypeDeclaration::node(VariableType::Container(ContainerType::Array), targ) }
-            / keyword("ref") targ:type_args()
+            / "[" _ targ:type_() _ size:(";" _ n:array_size() { n })? _ "]"
+                {
+                    let container = size.map_or(ContainerType::Array, ContainerType::StaticArray);
+                    TypeDeclaration::node(VariableType::Container(container), targ)
+                }
+            / (keyword("ref") / keyword("handle")) _ targ:type_args()
                 { TypeDeclaration::node(VariableType::Container(ContainerType::Ref), targ) }
-            / keyword("wref") targ:type_args()
+            / (keyword("wref") / keyword("whandle")) _ targ:type_args()
                 { TypeDeclaration::node(VariableType::Container(ContainerType::WeakRef), targ) }
-            / keyword("script_ref") targ:type_args()
+            / keyword("script_ref") _ targ:type_args()
                 { TypeDeclaration::node(VariableType::Container(ContainerType::ScriptRef), targ) }
+            / keyword("rRef") _ targ:type_args()
+                { TypeDeclaration::node(VariableType::Container(ContainerType::ResourceRef), targ) }
+            / keyword("raRef") _ targ:type_args()
+                { TypeDeclaration::node(VariableType::Container(ContainerType::ResourceAsyncRef), targ) }
+            // Compound types must come last, as container keywords are also valid identifiers
+            / comp:ident()
+                { TypeDeclaration::leaf(VariableType::Compound(comp)) }
         
 
         rule field_type() -> TypeDeclaration 
@@ -169,31 +532,446 @@ peg::parser! {
         
         pub rule field() -> FieldDefinition
             = comments:comments()?
-            _ annotations:space_sep(<annotation()>)
-            _ visibility:visibility()
-            _ qualifiers:space_sep(<field_qualifier()>)
+            _ start:position!()
+            annotations:space_sep(<annotation()>)
+            _ modifiers:space_sep(<modifier(<field_qualifier()>)>)
             _ keyword("let")
-            _ name:ident()
+            _ name:spanned_ident()
             _ type_:field_type()
-            _ ";"
-            { FieldDefinition { comments, annotations, visibility, qualifiers, name, type_ } }
+            _ ";" end:position!()
+            {
+                let (visibility, qualifiers) = split_modifiers(modifiers, diagnostics);
+                let (name, name_span) = name;
+                let span = index.span(start, end);
+                FieldDefinition { comments, annotations, visibility, qualifiers, name, name_span, type_, span }
+            }
 
         pub rule param() -> ParamDefinition
-            = qualifiers:space_sep(<param_qualifier()>)
-            _ name:ident()
+            = start:position!()
+            qualifiers:space_sep(<param_qualifier()>)
+            _ name:spanned_ident()
             _ type_:field_type()
-            { ParamDefinition { qualifiers, name, type_ } }
+            end:position!()
+            {
+                let (name, name_span) = name;
+                ParamDefinition { qualifiers, name, name_span, type_, span: index.span(start, end) }
+            }
 
         pub rule func() -> FuncDefinition
             = comments:comments()?
-            _ annotations:space_sep(<annotation()>)
-            _ visibility:visibility()
-            _ qualifiers:space_sep(<func_qualifier()>)
+            _ start:position!()
+            annotations:space_sep(<annotation()>)
+            _ modifiers:space_sep(<modifier(<func_qualifier()>)>)
             _ keyword("func")
-            _ name:ident()
+            _ name:spanned_ident()
             _ "(" _ params:comma_sep(<param()>) _ ")"
-            _ returns:func_type()
-            { FuncDefinition{ comments, annotations, visibility, qualifiers, name, params, returns } }
+            _ returns:func_type()?
+            _ body_span:(
+                body_start:position!() scope_block() body_end:position!() { Some(index.span(body_start, body_end)) }
+                / ";" { None }
+            )
+            end:position!()
+            {
+                let (visibility, qualifiers) = split_modifiers(modifiers, diagnostics);
+                let (name, name_span) = name;
+                let span = index.span(start, end);
+                // Functions without an explicit return type return Void
+                let returns = returns.unwrap_or(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::Void)));
+                FuncDefinition{ comments, annotations, visibility, qualifiers, name, name_span, params, returns, body_span, body: None, span }
+            }
+
+        rule member() -> MemberDefinition
+            = f:field() { MemberDefinition::Field(f) }
+            / f:func()  { MemberDefinition::Function(f) }
+
+        // Structs may be declared with `struct` in place of the `class` keyword, in which case the
+        // `struct` qualifier has already been consumed
+        pub rule class() -> ClassDefinition
+            = comments:comments()?
+            _ start:position!()
+            annotations:space_sep(<annotation()>)
+            _ modifiers:space_sep(<modifier(<class_qualifier()>)>)
+            _ class_keyword:keyword("class")?
+            _ name:spanned_ident()
+            _ base:(keyword("extends") _ b:spanned_ident() { b })?
+            _ "{" _ members:space_sep(<member()>) _ "}" end:position!()
+            {?
+                let is_struct = modifiers.iter().any(|(m, _)| matches!(m, Modifier::Qualifier(ClassQualifier::Struct)));
+                if class_keyword.is_none() && !is_struct {
+                    return Err("class");
+                }
+                let (visibility, qualifiers) = split_modifiers(modifiers, diagnostics);
+                let (name, name_span) = name;
+                let (base, base_span) = base.unzip();
+                let span = index.span(start, end);
+                Ok(ClassDefinition { comments, annotations, visibility, qualifiers, name, name_span, base, base_span, members, span })
+            }
+
+        // Integer literals, either decimal or `0x` prefixed hexadecimal. Hex values are read as
+        // unsigned so full-width bitmasks survive the trip into an `i64`.
+        rule dec_literal() -> i64
+            = n:$("-"? ['0'..='9']+) {? i64::from_str(n).or(Err("64-bit integer")) }
+        rule hex_literal() -> i64
+            = neg:"-"? "0" ['x' | 'X'] n:$(['0'..='9' | 'a'..='f' | 'A'..='F']+)
+            {?
+                u64::from_str_radix(n, 16)
+                    .map(|v| if neg.is_some() { (v as i64).wrapping_neg() } else { v as i64 })
+                    .or(Err("64-bit hex integer"))
+            }
+        rule int_literal() -> i64
+            = quiet!{ hex_literal() / dec_literal() } / expected!("Integer literal")
+
+
+        pub rule enum_value() -> EnumValDefinition
+            = comments:comments()?
+            _ start:position!()
+            name:spanned_ident()
+            _ "=" _ value:int_literal()
+            end:position!()
+            {
+                let (name, name_span) = name;
+                EnumValDefinition { comments, name, name_span, value, span: index.span(start, end) }
+            }
+
+        pub rule enum_() -> EnumDefinition
+            = comments:comments()?
+            _ start:position!()
+            annotations:space_sep(<annotation()>)
+            _ keyword("enum")
+            _ name:spanned_ident()
+            _ "{" _ values:comma_sep(<enum_value()>) _ ","? _ "}"
+            end:position!()
+            {
+                let (name, name_span) = name;
+                EnumDefinition { comments, annotations, name, name_span, values, span: index.span(start, end) }
+            }
+
+        rule module_path() -> Vec<Ident> = dot_sep(<ident()>)
+
+        pub rule module() -> ModuleDefinition
+            = comments:comments()?
+            _ start:position!()
+            keyword("module") _ path:module_path()
+            end:position!()
+            { ModuleDefinition { comments, path, span: index.span(start, end) } }
+
+        pub rule import() -> ImportDefinition
+            = start:position!()
+            keyword("import") _ path:module_path()
+            kind:(
+                _ "." _ "*" { ImportKind::All }
+                / _ "." _ "{" _ names:comma_sep(<ident()>) _ "}" { ImportKind::Selected(names) }
+            )?
+            end:position!()
+            {
+                let kind = kind.unwrap_or(ImportKind::Exact);
+                ImportDefinition { path, kind, span: index.span(start, end) }
+            }
+
+        // Any definition that may appear at the top level of a file. Comments that don't directly
+        // precede a definition are kept as free-standing comments.
+        pub rule definition() -> AnyDefinition
+            = d:detached_comments() { AnyDefinition::Comments(d) }
+            / d:module() { AnyDefinition::Module(d) }
+            / d:import() { AnyDefinition::Import(d) }
+            / d:enum_() { AnyDefinition::Enum(d) }
+            / d:class() { AnyDefinition::Class(d) }
+            / d:func()  { AnyDefinition::Func(d) }
+            / d:field() { AnyDefinition::Field(d) }
+            / d:comments() { AnyDefinition::Comments(d) }
+
+        pub rule file(name: &str) -> FileDefinition
+            = bom()? _ defs:space_sep(<definition()>) _
+            { new_file(name, defs, diagnostics) }
+
+        // Skips over a top-level item that couldn't be parsed, up to the end of its statement, the
+        // end of its scope block or the start of the next item, and returns the byte range that
+        // was skipped. A scope block that's never closed runs to the end of the input.
+        rule skipped_item() -> (usize, usize)
+            = start:position!()
+            quiet!{
+                &[_] (scope_string() / scope_comment() / !(";" / scope_begin() / next_item()) [_])*
+                (";" / scope_block() (_ ";")? / scope_begin() [_]*)?
+            }
+            end:position!()
+            { (start, end) }
+
+        // A line that starts another top-level item, which a skipped item doesn't run into
+        rule next_item()
+            = endl() inline_ws() (
+                "@" / "//" doc_line_start() / block_comment_start() / visibility() / class_qualifier() / func_qualifier() / field_qualifier()
+                / keyword("class") / keyword("enum") / keyword("func") / keyword("let") / keyword("module") / keyword("import")
+            )
+
+        // Every top-level item with the byte range it was parsed from, or no definition if it had
+        // to be skipped
+        pub rule file_items() -> Vec<(usize, usize, Option<AnyDefinition>)>
+            = bom()? _ items:space_sep(<
+                start:position!() d:definition() end:position!() { (start, end, Some(d)) }
+                / s:skipped_item() { (s.0, s.1, None) }
+            >) _
+            { items }
+
+        // Parses the single definition spanning `item` of a whole file, so its spans are located
+        // within the file
+        pub rule definition_at(item: &Range<usize>) -> AnyDefinition
+            = ##skip_to(item.start) d:definition() p:position!() ##skip_to_end()
+            {? if p == item.end { Ok(d) } else { Err("definition to end at its item") } }
+
+        // Function bodies, which are skipped whole by `scope_block` unless parsed on request by
+        // `parse_bodies`. Doc comments have no meaning within a body, so they're skipped along with
+        // the ordinary ones.
+        rule __() = quiet!{ (ws_char() / scope_comment())* }
+
+        rule block() -> Vec<Statement>
+            = "{" __ s:(statement() ** __) __ "}" { s }
+
+        pub rule statement() -> Statement
+            = start:position!() kind:statement_kind() end:position!()
+            { Statement { kind, span: index.span(start, end) } }
+
+        rule statement_kind() -> StatementKind
+            = keyword("let") __ name:ident()
+            __ type_:(":" __ t:type_() __ { t })?
+            value:("=" __ e:expression() __ { e })? ";"
+                { StatementKind::Let { name, type_, value } }
+            / keyword("return") __ value:expression()? __ ";"
+                { StatementKind::Return(value) }
+            / keyword("break") __ ";"
+                { StatementKind::Break }
+            / keyword("continue") __ ";"
+                { StatementKind::Continue }
+            / keyword("if") __ condition:expression() __ then:block()
+            else_:(__ keyword("else") __ e:(block() / &keyword("if") s:statement() { vec![s] }) { e })? block_end()
+                { StatementKind::If { condition, then, else_ } }
+            / keyword("switch") __ value:expression() __ "{" __ cases:(switch_case() ** __) __ "}" block_end()
+                {?
+                    if cases.iter().filter(|case| case.label.is_none()).count() > 1 {
+                        return Err("a single default case");
+                    }
+                    Ok(StatementKind::Switch { value, cases })
+                }
+            / keyword("while") __ condition:expression() __ body:block() block_end()
+                { StatementKind::While { condition, body } }
+            / keyword("for") __ name:ident() __ keyword("in") __ array:expression() __ body:block() block_end()
+                { StatementKind::For { name, array, body } }
+            / e:expression() __ ";"
+                { StatementKind::Expression(e) }
+
+        // Decompiled scripts end statements with blocks with a `;` too
+        rule block_end() = (__ ";")?
+
+        rule switch_case() -> SwitchCase
+            = start:position!()
+            label:(keyword("case") __ e:expression() { Some(e) } / keyword("default") { None })
+            __ ":" __ body:(statement() ** __)
+            end:position!()
+            { SwitchCase { label, body, span: index.span(start, end) } }
+
+        rule args() -> Vec<Expression>
+            = "(" __ args:(expression() ** (__ "," __)) __ ")" { args }
+
+        // Numbers are kept as written, so any type suffix such as `d` or `u` stays with them
+        rule number() -> Arc<str>
+            = quiet!{
+                n:$(
+                    "0" ['x' | 'X'] ['0'..='9' | 'a'..='f' | 'A'..='F']+
+                    / ['0'..='9']+ ("." ['0'..='9']+)? (['e' | 'E'] ['+' | '-']? ['0'..='9']+)? ['a'..='z']*
+                )
+                { Arc::from(n) }
+            }
+            / expected!("Number")
+
+        rule constant() -> Constant
+            = keyword("true")       { Constant::Bool(true) }
+            / keyword("false")      { Constant::Bool(false) }
+            / n:number()            { Constant::Number(n) }
+            / "n" s:string_literal() { Constant::String(StringKind::Name, s) }
+            / "r" s:string_literal() { Constant::String(StringKind::Resource, s) }
+            / "t" s:string_literal() { Constant::String(StringKind::TweakDBID, s) }
+            / "l" s:string_literal() { Constant::String(StringKind::LocalizationString, s) }
+            / "s\"" s:$(("\\(" anno_raw_content() ")" / "\\" [_] / !"\"" [_])*) "\""
+                { Constant::String(StringKind::Interpolated, Arc::from(s)) }
+            / s:string_literal()    { Constant::String(StringKind::String, s) }
+
+        rule assign_op() -> Option<BinaryOp>
+            = "=" !"="  { None }
+            / "+="      { Some(BinaryOp::Add) }
+            / "-="      { Some(BinaryOp::Sub) }
+            / "*="      { Some(BinaryOp::Mul) }
+            / "/="      { Some(BinaryOp::Div) }
+            / "%="      { Some(BinaryOp::Rem) }
+            / "&="      { Some(BinaryOp::BitAnd) }
+            / "|="      { Some(BinaryOp::BitOr) }
+            / "^="      { Some(BinaryOp::BitXor) }
+
+        // Operator levels from the loosest to the tightest binding, each of which parses the next
+        pub rule expression() -> Expression = assignment()
+
+        rule assignment() -> Expression
+            = start:position!() x:conditional()
+            y:(__ op:assign_op() __ y:assignment() { (op, y) })? end:position!()
+            {
+                match y {
+                    Some((op, y)) => Expression {
+                        kind: ExpressionKind::Assign(op, Box::new(x), Box::new(y)),
+                        span: index.span(start, end),
+                    },
+                    None => x,
+                }
+            }
+
+        rule conditional() -> Expression
+            = start:position!() x:or()
+            yz:(__ "?" __ y:expression() __ ":" __ z:conditional() { (y, z) })? end:position!()
+            {
+                match yz {
+                    Some((y, z)) => Expression {
+                        kind: ExpressionKind::Conditional(Box::new(x), Box::new(y), Box::new(z)),
+                        span: index.span(start, end),
+                    },
+                    None => x,
+                }
+            }
+
+        // Left-associative binary operators, a level each. Operators that are a prefix of another,
+        // such as `&` of `&&` and `&=`, only match when they aren't followed by the rest of the
+        // longer one.
+        rule or() -> Expression
+            = start:position!() x:and()
+            rest:(__ op:or_op() __ y:and() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule or_op() -> BinaryOp
+            = "||" { BinaryOp::Or }
+
+        rule and() -> Expression
+            = start:position!() x:bit_or()
+            rest:(__ op:and_op() __ y:bit_or() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule and_op() -> BinaryOp
+            = "&&" { BinaryOp::And }
+
+        rule bit_or() -> Expression
+            = start:position!() x:bit_xor()
+            rest:(__ op:bit_or_op() __ y:bit_xor() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule bit_or_op() -> BinaryOp
+            = "|" !['|' | '='] { BinaryOp::BitOr }
+
+        rule bit_xor() -> Expression
+            = start:position!() x:bit_and()
+            rest:(__ op:bit_xor_op() __ y:bit_and() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule bit_xor_op() -> BinaryOp
+            = "^" !"=" { BinaryOp::BitXor }
+
+        rule bit_and() -> Expression
+            = start:position!() x:equality()
+            rest:(__ op:bit_and_op() __ y:equality() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule bit_and_op() -> BinaryOp
+            = "&" !['&' | '='] { BinaryOp::BitAnd }
+
+        rule equality() -> Expression
+            = start:position!() x:comparison()
+            rest:(__ op:equality_op() __ y:comparison() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule equality_op() -> BinaryOp
+            = "==" { BinaryOp::Eq }
+            / "!=" { BinaryOp::Ne }
+
+        rule comparison() -> Expression
+            = start:position!() x:additive()
+            rest:(__ op:comparison_op() __ y:additive() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule comparison_op() -> BinaryOp
+            = "<=" { BinaryOp::Le }
+            / ">=" { BinaryOp::Ge }
+            / "<" { BinaryOp::Lt }
+            / ">" { BinaryOp::Gt }
+
+        rule additive() -> Expression
+            = start:position!() x:multiplicative()
+            rest:(__ op:additive_op() __ y:multiplicative() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule additive_op() -> BinaryOp
+            = "+" !"=" { BinaryOp::Add }
+            / "-" !"=" { BinaryOp::Sub }
+
+        rule multiplicative() -> Expression
+            = start:position!() x:cast()
+            rest:(__ op:multiplicative_op() __ y:cast() end:position!() { (op, y, end) })*
+            { binary(index, start, x, rest) }
+        rule multiplicative_op() -> BinaryOp
+            = "*" !"=" { BinaryOp::Mul }
+            / "/" !"=" { BinaryOp::Div }
+            / "%" !"=" { BinaryOp::Rem }
+
+        rule cast() -> Expression
+            = start:position!() x:unary()
+            types:(__ keyword("as") __ t:type_() end:position!() { (t, end) })*
+            {
+                types.into_iter().fold(x, |x, (t, end)| Expression {
+                    kind: ExpressionKind::Cast(Box::new(x), t),
+                    span: index.span(start, end),
+                })
+            }
+
+        rule unary() -> Expression
+            = start:position!() op:unary_op() __ x:unary() end:position!()
+                { Expression { kind: ExpressionKind::Unary(op, Box::new(x)), span: index.span(start, end) } }
+            / postfix()
+
+        rule unary_op() -> UnaryOp
+            = "-" { UnaryOp::Neg }
+            / "!" { UnaryOp::Not }
+            / "~" { UnaryOp::BitNot }
+
+        // Member accesses, indexing and calls, applied in the order written
+        rule postfix() -> Expression
+            = start:position!() x:primary() ops:(__ op:postfix_op() end:position!() { (op, end) })*
+            {
+                ops.into_iter().fold(x, |x, (op, end)| {
+                    let x = Box::new(x);
+                    let kind = match op {
+                        Postfix::Member(name) => ExpressionKind::Member(x, name),
+                        Postfix::Index(i) => ExpressionKind::Index(x, Box::new(i)),
+                        Postfix::Call(args) => ExpressionKind::Call { callee: x, type_args: vec![], args },
+                    };
+                    Expression { kind, span: index.span(start, end) }
+                })
+            }
+
+        rule postfix_op() -> Postfix
+            = "." __ name:ident() { Postfix::Member(name) }
+            / "[" __ i:expression() __ "]" { Postfix::Index(i) }
+            / args:args() { Postfix::Call(args) }
+
+        rule primary() -> Expression
+            = start:position!() kind:primary_kind() end:position!()
+            { Expression { kind, span: index.span(start, end) } }
+
+        rule primary_kind() -> ExpressionKind
+            = c:constant() { ExpressionKind::Constant(c) }
+            / keyword("null") { ExpressionKind::Null }
+            / keyword("this") { ExpressionKind::This }
+            / keyword("super") { ExpressionKind::Super }
+            / keyword("new") __ name:ident() __ args:args() { ExpressionKind::New(name, args) }
+            / "[" __ items:(expression() ** (__ "," __)) __ "]" { ExpressionKind::Array(items) }
+            / "(" __ e:expression() __ ")" { e.kind }
+            // Calls with type arguments are told apart from comparisons by the `(` after the `>`
+            / callee_start:position!() name:ident() callee_end:position!()
+            __ "<" __ type_args:(type_() ** (__ "," __)) __ ">" __ args:args()
+                {
+                    let callee = Expression { kind: ExpressionKind::Ident(name), span: index.span(callee_start, callee_end) };
+                    ExpressionKind::Call { callee: Box::new(callee), type_args, args }
+                }
+            / name:ident() { ExpressionKind::Ident(name) }
+
+        // Parses the body spanning `item` of a whole file, so its spans are located within the file
+        pub rule body_at(item: &Range<usize>) -> Vec<Statement>
+            = ##skip_to(item.start) b:block() p:position!() ##skip_to_end()
+            {? if p == item.end { Ok(b) } else { Err("body to end at its closing brace") } }
 
         pub rule field_traced() -> FieldDefinition = traced(<field()>)
         pub rule type_traced() -> TypeDeclaration = traced(<type_()>)
@@ -203,10 +981,16 @@ peg::parser! {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diagnostic::Severity;
+
+    /// Type as parsed from the given span
+    fn spanned(type_: TypeDeclaration, span: Span) -> TypeDeclaration {
+        TypeDeclaration { span: Some(span), ..type_ }
+    }
 
     #[test]
     fn parse_comment_line() {
-        let comment = redscript::comments("/// Line comment\n").unwrap();
+        let comment = crate::parse_comments("/// Line comment\n").unwrap();
 
         assert_eq!(
             format!("{:?}", comment.lines),
@@ -216,7 +1000,7 @@ mod tests {
 
     #[test]
     fn parse_comment_block() {
-        let comments = redscript::comments("/**
+        let comments = crate::parse_comments("/**
 Unprefixed line
  * Prefixed line
 * Also prefixed line
@@ -244,62 +1028,870 @@ No whitespace at end*/").unwrap();
 
     #[test]
     fn parse_field_uncommented() {
-        let field = redscript::field_traced("private static const let m_field: Int32;").unwrap();
+        let source = "private static const let m_field: Int32;";
+        let field = redscript::field_traced(source, &LineIndex::new(source), &RefCell::default()).unwrap();
 
         assert_eq!(
             format!("{:?}", field),
             format!("{:?}", FieldDefinition {
                 comments: None,
                 annotations: vec![],
-                visibility: Visibility::Private,
+                visibility: Some(Visibility::Private),
                 qualifiers: vec![FieldQualifier::Static, FieldQualifier::Const],
-                name: Ident::new("m_field".to_string()),
-                type_: TypeDeclaration::leaf(VariableType::Scalar(ScalarType::I32))
+                name: Ident::new("m_field"),
+                name_span: Span::new(25, 32, 1, 26),
+                type_: spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::I32)), Span::new(34, 39, 1, 35)),
+                span: Span::new(0, 40, 1, 1)
             })
         );
     }
 
     #[test]
     fn parse_type_args() {
-        let scalar = redscript::type_traced("array<CName> butts").unwrap();
+        let source = "array<CName>";
+        let array = redscript::type_traced(source, &LineIndex::new(source), &RefCell::default()).unwrap();
         assert_eq!(
-            format!("{:?}", scalar),
-            format!("{:?}", TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName)))
+            format!("{:?}", array),
+            format!("{:?}", spanned(
+                TypeDeclaration::node(
+                    VariableType::Container(ContainerType::Array),
+                    spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName)), Span::new(6, 11, 1, 7))
+                ),
+                Span::new(0, 12, 1, 1)
+            ))
         )
     }
 
     #[test]
     fn parse_field_commented() {
-        let field = redscript::field_traced(
-            r#"/**
+        let source = r#"/**
               * This field has several comments
               * with a bunch of whitespace
               * and **MARKDOWN** content
               *   - Like this list item
               */
             @addField(GameObject)
-            protected native final let m_names: array<CName>;"#).unwrap();
+            protected native final let m_names: array<CName>;"#;
+        let field = redscript::field_traced(source, &LineIndex::new(source), &RefCell::default()).unwrap();
 
         assert_eq!(
             format!("{:?}", field),
             format!("{:?}", FieldDefinition {
-                comments: Some(CommentDefinition {
-                    lines: vec![
-                        Rc::new("This field has several comments".to_string()),
-                        Rc::new("with a bunch of whitespace".to_string()),
-                        Rc::new("and **MARKDOWN** content".to_string()),
-                        Rc::new("  - Like this list item".to_string())
-                    ]
-                }),
-                annotations: vec![Annotation::AddField(Ident::new("GameObject".to_string()))],
-                visibility: Visibility::Protected,
+                comments: Some(CommentDefinition::new(
+                    vec![
+                        Arc::from("This field has several comments"),
+                        Arc::from("with a bunch of whitespace"),
+                        Arc::from("and **MARKDOWN** content"),
+                        Arc::from("  - Like this list item")
+                    ],
+                    Span::new(0, 192, 1, 1)
+                )),
+                annotations: vec![Annotation {
+                    kind: AnnotationKind::AddField(Ident::new("GameObject")),
+                    ident_span: Some(Span::new(215, 225, 7, 23)),
+                    span: Span::new(205, 226, 7, 13)
+                }],
+                visibility: Some(Visibility::Protected),
                 qualifiers: vec![FieldQualifier::Native, FieldQualifier::Final],
-                name: Ident::new("m_names".to_string()),
-                type_: TypeDeclaration::node(
-                    VariableType::Container(ContainerType::Array),
-                    TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName))
-                )
+                name: Ident::new("m_names"),
+                name_span: Span::new(266, 273, 8, 40),
+                type_: spanned(
+                    TypeDeclaration::node(
+                        VariableType::Container(ContainerType::Array),
+                        spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName)), Span::new(281, 286, 8, 55))
+                    ),
+                    Span::new(275, 287, 8, 49)
+                ),
+                span: Span::new(205, 288, 7, 13)
             })
         );
     }
+
+    #[test]
+    fn parse_enum() {
+        let enum_ = crate::parse_enum(
+            r#"/// Movement state of an actor
+            enum moveMovementType {
+                /// Not moving at all
+                Stand = 0,
+                Walk = 1,
+                Sprint = 0x10,
+                Invalid = -1,
+            }"#).unwrap();
+
+        assert_eq!(
+            format!("{:?}", enum_),
+            format!("{:?}", EnumDefinition {
+                comments: Some(CommentDefinition::new(vec![Arc::from("Movement state of an actor")], Span::new(0, 30, 1, 1))),
+                annotations: vec![],
+                name: Ident::new("moveMovementType"),
+                name_span: Span::new(48, 64, 2, 18),
+                values: vec![
+                    EnumValDefinition {
+                        comments: Some(CommentDefinition::new(vec![Arc::from("Not moving at all")], Span::new(83, 104, 3, 17))),
+                        name: Ident::new("Stand"),
+                        name_span: Span::new(121, 126, 4, 17),
+                        value: 0,
+                        span: Span::new(121, 130, 4, 17)
+                    },
+                    EnumValDefinition {
+                        comments: None,
+                        name: Ident::new("Walk"),
+                        name_span: Span::new(148, 152, 5, 17),
+                        value: 1,
+                        span: Span::new(148, 156, 5, 17)
+                    },
+                    EnumValDefinition {
+                        comments: None,
+                        name: Ident::new("Sprint"),
+                        name_span: Span::new(174, 180, 6, 17),
+                        value: 16,
+                        span: Span::new(174, 187, 6, 17)
+                    },
+                    EnumValDefinition {
+                        comments: None,
+                        name: Ident::new("Invalid"),
+                        name_span: Span::new(205, 212, 7, 17),
+                        value: -1,
+                        span: Span::new(205, 217, 7, 17)
+                    },
+                ],
+                span: Span::new(43, 232, 2, 13)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_enum_hex_bitmask() {
+        let enum_ = crate::parse_enum("enum gameEFlags { None = 0x0, All = 0xFFFFFFFFFFFFFFFF, Neg = -0x2 }").unwrap();
+        let values: Vec<i64> = enum_.values.iter().map(|v| v.value).collect();
+
+        assert_eq!(values, vec![0, -1, -2]);
+    }
+
+    #[test]
+    fn parse_class() {
+        let class = crate::parse_class(
+            r#"/// Base for all game objects
+            public abstract native class GameObject extends Entity {
+                /// Display name
+                protected native let m_name: CName;
+                private let m_tags: array<CName>;
+                public final func GetName() -> CName {
+                    return this.m_name;
+                }
+            }"#).unwrap();
+
+        assert_eq!(
+            format!("{:?}", class),
+            format!("{:?}", ClassDefinition {
+                comments: Some(CommentDefinition::new(vec![Arc::from("Base for all game objects")], Span::new(0, 29, 1, 1))),
+                annotations: vec![],
+                visibility: Some(Visibility::Public),
+                qualifiers: vec![ClassQualifier::Abstract, ClassQualifier::Native],
+                name: Ident::new("GameObject"),
+                name_span: Span::new(71, 81, 2, 42),
+                base: Some(Ident::new("Entity")),
+                base_span: Some(Span::new(90, 96, 2, 61)),
+                members: vec![
+                    MemberDefinition::Field(FieldDefinition {
+                        comments: Some(CommentDefinition::new(vec![Arc::from("Display name")], Span::new(115, 131, 3, 17))),
+                        annotations: vec![],
+                        visibility: Some(Visibility::Protected),
+                        qualifiers: vec![FieldQualifier::Native],
+                        name: Ident::new("m_name"),
+                        name_span: Span::new(169, 175, 4, 38),
+                        type_: spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName)), Span::new(177, 182, 4, 46)),
+                        span: Span::new(148, 183, 4, 17)
+                    }),
+                    MemberDefinition::Field(FieldDefinition {
+                        comments: None,
+                        annotations: vec![],
+                        visibility: Some(Visibility::Private),
+                        qualifiers: vec![],
+                        name: Ident::new("m_tags"),
+                        name_span: Span::new(212, 218, 5, 29),
+                        type_: spanned(
+                            TypeDeclaration::node(
+                                VariableType::Container(ContainerType::Array),
+                                spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName)), Span::new(226, 231, 5, 43))
+                            ),
+                            Span::new(220, 232, 5, 37)
+                        ),
+                        span: Span::new(200, 233, 5, 17)
+                    }),
+                    MemberDefinition::Function(FuncDefinition {
+                        comments: None,
+                        annotations: vec![],
+                        visibility: Some(Visibility::Public),
+                        qualifiers: vec![FuncQualifier::Final],
+                        name: Ident::new("GetName"),
+                        name_span: Span::new(268, 275, 6, 35),
+                        params: vec![],
+                        returns: spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::CName)), Span::new(281, 286, 6, 48)),
+                        body_span: Some(Span::new(287, 346, 6, 54)),
+                        body: None,
+                        span: Span::new(250, 346, 6, 17)
+                    }),
+                ],
+                span: Span::new(42, 360, 2, 13)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_struct() {
+        let class = crate::parse_class("public native struct Vector4 { public native let X: Float; }").unwrap();
+
+        assert_eq!(
+            format!("{:?}", class.qualifiers),
+            format!("{:?}", vec![ClassQualifier::Native, ClassQualifier::Struct])
+        );
+        assert!(class.base.is_none());
+        assert_eq!(class.members.len(), 1);
+    }
+
+    #[test]
+    fn parse_file() {
+        let file = crate::parse_file("game/object.script",
+            r#"
+            /// Free-standing comment
+
+            enum gameEActionStatus { STATUS_INVALID = 0, STATUS_BOUND = 1 }
+
+            /// An object
+            public class GameObject extends Entity {
+                private let m_name: CName;
+            }
+
+            public static native func OperatorAdd(a: Int32, b: Int32) -> Int32;
+
+            @addField(GameObject)
+            public let m_extra: Bool;
+            "#).unwrap();
+
+        assert_eq!(format!("{:?}", file.name), format!("{:?}", Ident::new("game/object.script")));
+
+        let kinds: Vec<&str> = file.defs.iter().map(|def| match def {
+            AnyDefinition::Comments(_) => "comments",
+            AnyDefinition::Enum(_) => "enum",
+            AnyDefinition::Class(_) => "class",
+            AnyDefinition::Func(_) => "func",
+            AnyDefinition::Field(_) => "field",
+            _ => "other",
+        }).collect();
+
+        assert_eq!(kinds, vec!["comments", "enum", "class", "func", "field"]);
+
+        // The blank line keeps the free-standing comment from documenting the enum
+        match &file.defs[1] {
+            AnyDefinition::Enum(enum_) => assert!(enum_.comments.is_none()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_plain_comments() {
+        let file = crate::parse_file("test.reds",
+            r#"
+            // Decompiled from the game scripts
+            /* enum Removed { A = 0 } */
+            ////////////////////////////////
+            /// Documented
+            // an ordinary note between a doc comment and its class
+            public class Foo {
+                //////// Fields
+                /**/ private let m_a: Int32; // trailing note
+                /// Documented field
+                private let m_b: Int32;
+                public func Bar() { // }
+                    let s = "/* { */";
+                    /* } */
+                }
+            }
+            "#).unwrap();
+
+        assert_eq!(file.defs.len(), 1);
+        let class = match &file.defs[0] {
+            AnyDefinition::Class(class) => class,
+            def => panic!("expected a class, got {:?}", def),
+        };
+        assert_eq!(class.comments.as_ref().unwrap().lines, vec![Arc::from("Documented")]);
+        match &class.members[..] {
+            [MemberDefinition::Field(a), MemberDefinition::Field(b), MemberDefinition::Function(_)] => {
+                assert!(a.comments.is_none());
+                assert_eq!(b.comments.as_ref().unwrap().lines, vec![Arc::from("Documented field")]);
+            }
+            members => panic!("unexpected members {:?}", members),
+        }
+    }
+
+    #[test]
+    fn parse_func_body() {
+        let func = crate::parse_func(
+            r#"public func Log(message: String) {
+                if this.m_enabled {
+                    LogChannel(n"DEBUG", "} is not the end of the body {");
+                }
+            }"#).unwrap();
+
+        assert_eq!(
+            format!("{:?}", func.returns),
+            format!("{:?}", TypeDeclaration::leaf(VariableType::Scalar(ScalarType::Void)))
+        );
+        assert_eq!(func.params.len(), 1);
+    }
+
+    #[test]
+    fn parse_func_native() {
+        let func = crate::parse_func("public static native func GetGameInstance() -> GameInstance;").unwrap();
+
+        assert_eq!(
+            format!("{:?}", func.qualifiers),
+            format!("{:?}", vec![FuncQualifier::Static, FuncQualifier::Native])
+        );
+        assert_eq!(
+            format!("{:?}", func.returns),
+            format!("{:?}", spanned(TypeDeclaration::leaf(VariableType::Compound(Ident::new("GameInstance"))), Span::new(47, 59, 1, 48)))
+        );
+
+        assert!(func.body_span.is_none());
+
+        // A declaration must end with either a body or a semicolon
+        assert!(crate::parse_func("public native func GetGameInstance() -> GameInstance").is_err());
+    }
+
+    /// Expression in prefix notation, so its structure can be compared at a glance
+    fn prefix(expr: &Expression) -> String {
+        let list = |exprs: &[Expression]| exprs.iter().map(prefix).collect::<Vec<_>>().join(" ");
+        match &expr.kind {
+            ExpressionKind::Ident(name) => name.to_string(),
+            ExpressionKind::Constant(Constant::Bool(b)) => b.to_string(),
+            ExpressionKind::Constant(Constant::Number(n)) => n.to_string(),
+            ExpressionKind::Constant(Constant::String(kind, s)) => format!("{:?}\"{}\"", kind, s),
+            ExpressionKind::This => "this".to_string(),
+            ExpressionKind::Super => "super".to_string(),
+            ExpressionKind::Null => "null".to_string(),
+            ExpressionKind::Array(items) => format!("[{}]", list(items)),
+            ExpressionKind::Member(x, name) => format!("(. {} {})", prefix(x), name),
+            ExpressionKind::Index(x, i) => format!("([] {} {})", prefix(x), prefix(i)),
+            ExpressionKind::Call { callee, type_args, args } if type_args.is_empty() =>
+                format!("(call {} {})", prefix(callee), list(args)),
+            ExpressionKind::Call { callee, type_args, args } =>
+                format!("(call {}<{}> {})", prefix(callee), type_args[0], list(args)),
+            ExpressionKind::New(name, args) => format!("(new {} {})", name, list(args)),
+            ExpressionKind::Cast(x, type_) => format!("(as {} {})", prefix(x), type_),
+            ExpressionKind::Unary(op, x) => format!("({:?} {})", op, prefix(x)),
+            ExpressionKind::Binary(op, x, y) => format!("({:?} {} {})", op, prefix(x), prefix(y)),
+            ExpressionKind::Assign(op, x, y) => format!("(={:?} {} {})", op, prefix(x), prefix(y)),
+            ExpressionKind::Conditional(x, y, z) => format!("(? {} {} {})", prefix(x), prefix(y), prefix(z)),
+        }
+    }
+
+    #[test]
+    fn parse_expression_precedence() {
+        let expr = |source| prefix(&crate::parse_expression(source).unwrap());
+
+        assert_eq!(expr("a = b + c * -d.E(1) as F"), "(=None a (Add b (Mul c (as (Neg (call (. d E) 1)) F))))");
+        assert_eq!(expr("a - b - c"), "(Sub (Sub a b) c)");
+        assert_eq!(expr("a += b ? c : d ? e : f"), "(=Some(Add) a (? b c (? d e f)))");
+        assert_eq!(expr("a || b && c == d < e | f & g"), "(Or a (And b (BitOr (Eq c (Lt d e)) (BitAnd f g))))");
+        assert_eq!(expr("!this.m_items[i + 1].IsDefined()"), "(Not (call (. ([] (. this m_items) (Add i 1)) IsDefined) ))");
+        assert_eq!(expr("(a + b) * c"), "(Mul (Add a b) c)");
+        assert_eq!(expr("!!a as B as C"), "(as (as (Not (Not a)) B) C)");
+        assert_eq!(expr("Cast<Float>(x) < y"), "(Lt (call Cast<Float> x) y)");
+        assert_eq!(expr("new Vector4(1.0, 0x1F, 2u)"), "(new Vector4 1.0 0x1F 2u)");
+        assert_eq!(
+            expr(r#"[n"Name", r"base\a.ent", t"Items.A", l"Key", "}"]"#),
+            r#"[Name"Name" Resource"base\a.ent" TweakDBID"Items.A" LocalizationString"Key" String"}"]"#
+        );
+        assert_eq!(expr(r#"s"Hi \(f("x", (y))), \"you\"" + z"#), r#"(Add Interpolated"Hi \(f("x", (y))), \"you\"" z)"#);
+
+        let expr = crate::parse_expression("a.B(c)").unwrap();
+        assert_eq!(expr.span, Span::new(0, 6, 1, 1));
+        match expr.kind {
+            ExpressionKind::Call { callee, .. } => assert_eq!(callee.span, Span::new(0, 3, 1, 1)),
+            kind => panic!("expected a call, not {:?}", kind),
+        }
+
+        let expr = crate::parse_expression("a - b - -c").unwrap();
+        assert_eq!(expr.span, Span::new(0, 10, 1, 1));
+        match expr.kind {
+            ExpressionKind::Binary(_, x, y) => {
+                assert_eq!(x.span, Span::new(0, 5, 1, 1));
+                assert_eq!(y.span, Span::new(8, 10, 1, 9));
+            }
+            kind => panic!("expected a subtraction, not {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parse_statements() {
+        let statement = crate::parse_statement(
+            r#"if IsDefined(player) {
+                let items: array<ref<Item>> = player.GetItems(); // Comment
+                /// Doc comments are ignored within bodies
+                for item in items {
+                    switch item.GetType() {
+                        case ItemType.Weapon:
+                        case ItemType.Armor:
+                            this.Equip(item);
+                            break;
+                        default:
+                            continue;
+                    }
+                }
+            } else if count > 0 {
+                while count > 0 { count -= 1; };
+            } else {
+                return;
+            }"#).unwrap();
+
+        let (then, else_) = match statement.kind {
+            StatementKind::If { condition, then, else_ } => {
+                assert_eq!(prefix(&condition), "(call IsDefined player)");
+                (then, else_.unwrap())
+            }
+            kind => panic!("expected an if, not {:?}", kind),
+        };
+
+        assert!(matches!(&then[0].kind, StatementKind::Let { type_: Some(_), value: Some(_), .. }));
+        assert_eq!(then[0].span.line, 2);
+        match &then[1].kind {
+            StatementKind::For { name, array, body } => {
+                assert_eq!(name.as_str(), "item");
+                assert_eq!(prefix(array), "items");
+                match &body[0].kind {
+                    StatementKind::Switch { cases, .. } => {
+                        assert_eq!(cases.len(), 3);
+                        assert!(cases[0].body.is_empty());
+                        assert_eq!(cases[1].label.as_ref().map(prefix).as_deref(), Some("(. ItemType Armor)"));
+                        assert!(matches!(cases[1].body[1].kind, StatementKind::Break));
+                        assert!(cases[2].label.is_none());
+                        assert!(matches!(cases[2].body[..], [Statement { kind: StatementKind::Continue, .. }]));
+                    }
+                    kind => panic!("expected a switch, not {:?}", kind),
+                }
+            }
+            kind => panic!("expected a for, not {:?}", kind),
+        }
+
+        // The `else if` is an `else` block holding only the nested `if`
+        match &else_[..] {
+            [Statement { kind: StatementKind::If { then, else_: Some(else_), .. }, .. }] => {
+                assert!(matches!(then[0].kind, StatementKind::While { .. }));
+                assert!(matches!(else_[..], [Statement { kind: StatementKind::Return(None), .. }]));
+            }
+            else_ => panic!("expected an else if, not {:?}", else_),
+        }
+
+        // The default case may come anywhere, but only once
+        match crate::parse_statement("switch x { default: case 1: break; }").unwrap().kind {
+            StatementKind::Switch { cases, .. } => {
+                let labels: Vec<Option<String>> = cases.iter().map(|case| case.label.as_ref().map(prefix)).collect();
+                assert_eq!(labels, vec![None, Some("1".to_string())]);
+            }
+            kind => panic!("expected a switch, not {:?}", kind),
+        }
+        assert!(crate::parse_statement("switch x { default: break; default: break; }").is_err());
+    }
+
+    #[test]
+    fn parse_bodies() {
+        let source = "class A {
+    public func B() -> Int32 {
+        return this.C(1);
+    }
+    public native func D();
+    func E() { let x = 1 +; }
+}";
+        let (mut file, diagnostics) = crate::parse_file_recovering("a.reds", source);
+        assert!(diagnostics.is_empty());
+
+        let diagnostics = crate::parse_bodies(&mut file, source);
+        let funcs: Vec<&FuncDefinition> = match &file.defs[0] {
+            AnyDefinition::Class(class) => class.members.iter().filter_map(|member| match member {
+                MemberDefinition::Function(func) => Some(func),
+                _ => None,
+            }).collect(),
+            def => panic!("expected a class, not {:?}", def),
+        };
+
+        // Spans are located within the whole file
+        let body = funcs[0].body.as_ref().unwrap();
+        assert_eq!(body[0].span, Span::new(49, 66, 3, 9));
+        assert!(funcs[1].body.is_none());
+        assert!(funcs[2].body.is_none());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].span.line, 6);
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let err = crate::parse_file("test.reds", "enum Broken {\n    A = 0,\n    B = ,\n}").unwrap_err();
+
+        assert_eq!((err.line, err.column, err.offset), (3, 9, 33));
+        assert_eq!(err.expected, vec!["Integer literal"]);
+        assert_eq!(err.to_string(), "3:9: expected Integer literal");
+
+        // A byte order mark isn't a column
+        let err = crate::parse_file("test.reds", "\u{FEFF}enum = 0").unwrap_err();
+        assert_eq!((err.line, err.column, err.offset), (1, 6, 8));
+    }
+
+    #[test]
+    fn parse_type_public() {
+        let type_ = parse_type("array<wref<GameObject>>").unwrap();
+
+        assert_eq!(
+            format!("{:?}", type_),
+            format!("{:?}", spanned(
+                TypeDeclaration::node(
+                    VariableType::Container(ContainerType::Array),
+                    spanned(
+                        TypeDeclaration::node(
+                            VariableType::Container(ContainerType::WeakRef),
+                            spanned(TypeDeclaration::leaf(VariableType::Compound(Ident::new("GameObject"))), Span::new(11, 21, 1, 12))
+                        ),
+                        Span::new(6, 22, 1, 7)
+                    )
+                ),
+                Span::new(0, 23, 1, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_type_extended() {
+        let type_ = parse_type("[ raRef < CResource > ; 4 ]").unwrap();
+        assert_eq!(
+            format!("{:?}", type_),
+            format!("{:?}", spanned(
+                TypeDeclaration::node(
+                    VariableType::Container(ContainerType::StaticArray(4)),
+                    spanned(
+                        TypeDeclaration::node(
+                            VariableType::Container(ContainerType::ResourceAsyncRef),
+                            spanned(TypeDeclaration::leaf(VariableType::Compound(Ident::new("CResource"))), Span::new(10, 19, 1, 11))
+                        ),
+                        Span::new(2, 21, 1, 3)
+                    )
+                ),
+                Span::new(0, 27, 1, 1)
+            ))
+        );
+
+        let type_ = parse_type("[whandle<Uint8>]").unwrap();
+        assert_eq!(
+            format!("{:?}", type_),
+            format!("{:?}", spanned(
+                TypeDeclaration::node(
+                    VariableType::Container(ContainerType::Array),
+                    spanned(
+                        TypeDeclaration::node(
+                            VariableType::Container(ContainerType::WeakRef),
+                            spanned(TypeDeclaration::leaf(VariableType::Scalar(ScalarType::U8)), Span::new(9, 14, 1, 10))
+                        ),
+                        Span::new(1, 15, 1, 2)
+                    )
+                ),
+                Span::new(0, 16, 1, 1)
+            ))
+        );
+
+        assert!(matches!(parse_type("LocalizationString").unwrap().type_, VariableType::Scalar(ScalarType::LocalizationString)));
+        assert!(matches!(parse_type("NodeRef").unwrap().type_, VariableType::Scalar(ScalarType::NodeRef)));
+        assert!(matches!(parse_type("CRUID").unwrap().type_, VariableType::Scalar(ScalarType::CRUID)));
+        assert!(matches!(parse_type("Int16").unwrap().type_, VariableType::Scalar(ScalarType::I16)));
+        // Natives such as Quaternion are ordinary compound types
+        assert!(matches!(parse_type("Quaternion").unwrap().type_, VariableType::Compound(_)));
+        assert!(parse_type("[Int32; -1]").is_err());
+    }
+
+    #[test]
+    fn parse_annotations() {
+        let class = crate::parse_class(r#"
+            @if(ModuleExists("Codeware.UI"))
+            public class Panel {
+                @wrapMethod(PlayerPuppet)
+                protected cb func OnGameAttached() -> Bool {}
+
+                @runtimeProperty("offset", n"m_offset")
+                public let m_offset: Int32;
+            }"#).unwrap();
+
+        let annotations = |source: &str| -> Vec<String> {
+            let field = crate::parse_field(&format!("{} let m_field: Int32;", source)).unwrap();
+            field.annotations.iter().map(|annotation| annotation.to_string()).collect()
+        };
+        assert_eq!(annotations(r#"@runtimeProperty("offset", 5) @someAnno(1.5, -2, true)"#), vec![
+            r#"@runtimeProperty("offset", 5)"#,
+            "@someAnno(1.5, -2, true)",
+        ]);
+        // Anything else is kept as written rather than failing the definition
+        assert_eq!(annotations(r#"@odd(a + (b), ")")"#), vec![r#"@odd(a + (b), ")")"#]);
+
+        let field = crate::parse_field(r#"@if(!ModuleExists("X")) let m_field: Int32;"#).unwrap();
+        assert_eq!(
+            format!("{:?}", field.annotations[0].kind),
+            format!("{:?}", AnnotationKind::If(AnnotationArg::Not(Box::new(AnnotationArg::Call(
+                Ident::new("ModuleExists"),
+                vec![AnnotationArg::String(Arc::from("X"))]
+            )))))
+        );
+        let field = crate::parse_field("@if(Version() >= 2) let m_field: Int32;").unwrap();
+        assert!(matches!(field.annotations[0].condition(), Some(AnnotationArg::Raw(raw)) if raw.as_ref() == "Version() >= 2"));
+
+        assert_eq!(
+            format!("{:?}", class.annotations[0].kind),
+            format!("{:?}", AnnotationKind::If(AnnotationArg::Call(
+                Ident::new("ModuleExists"),
+                vec![AnnotationArg::String(Arc::from("Codeware.UI"))]
+            )))
+        );
+        assert_eq!(class.annotations[0].condition().unwrap().to_string(), r#"ModuleExists("Codeware.UI")"#);
+
+        match &class.members[..] {
+            [MemberDefinition::Function(func), MemberDefinition::Field(field)] => {
+                assert!(matches!(&func.annotations[0].kind, AnnotationKind::WrapMethod(class) if class.as_str() == "PlayerPuppet"));
+                assert_eq!(func.annotations[0].span, Span::new(95, 120, 4, 17));
+                assert_eq!(func.annotations[0].ident_span, Some(Span::new(107, 119, 4, 29)));
+                assert_eq!(field.annotations[0].ident_span, Some(Span::new(201, 216, 7, 18)));
+                match &field.annotations[0].kind {
+                    AnnotationKind::Unsupported(name, args) => {
+                        assert_eq!(name.as_str(), "runtimeProperty");
+                        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                        assert_eq!(args, vec![r#""offset""#, r#"n"m_offset""#]);
+                    }
+                    kind => panic!("unexpected annotation {:?}", kind),
+                }
+            }
+            members => panic!("unexpected members {:?}", members),
+        }
+    }
+
+    #[test]
+    fn parse_crlf_bom() {
+        let source = "\u{FEFF}/// First line\r\n/// Second line\r\nenum A { B = 0 }\r\n\r\n/**\r\n * Block\r\n */\r\n\r\n";
+        let file = crate::parse_file("test.reds", source).unwrap();
+
+        match &file.defs[..] {
+            [AnyDefinition::Enum(enum_), AnyDefinition::Comments(comments)] => {
+                let doc = enum_.comments.as_ref().unwrap();
+                assert_eq!(doc.lines, vec![Arc::from("First line"), Arc::from("Second line")]);
+                // Spans are offsets into the text as given, byte order mark included, although the
+                // mark isn't a column
+                assert_eq!(doc.span, Span::new(3, 34, 1, 1));
+                assert_eq!(enum_.span, Span::new(36, 52, 3, 1));
+                assert_eq!(comments.lines, vec![Arc::from("Block")]);
+            }
+            defs => panic!("unexpected definitions {:?}", defs),
+        }
+    }
+
+    #[test]
+    fn parse_spans_multibyte() {
+        // Columns count characters, so the multi-byte comment doesn't shift the enum's position
+        let file = crate::parse_file("test.reds", "/// Ünïcödé\nenum A { B = 0 }\n\n/// Ünïcödé\n").unwrap();
+
+        match &file.defs[..] {
+            [AnyDefinition::Enum(enum_), AnyDefinition::Comments(comments)] => {
+                assert_eq!(enum_.span, Span::new(16, 32, 2, 1));
+                assert_eq!(enum_.name_span, Span::new(21, 22, 2, 6));
+                assert_eq!(comments.span, Span::new(34, 49, 4, 1));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_file_recovering() {
+        let (file, diagnostics) = crate::parse_file_recovering("test.reds", r#"
+enum First { A = 0 }
+
+public class Broken extends {
+    public func Method() -> Void {
+        Log("{");
+    }
+}
+
+public func Unknown(a: Int32) -> Int32 @ {}
+
+enum Last { B = 1 }
+"#);
+
+        let names: Vec<&str> = file.defs.iter().map(|def| match def {
+            AnyDefinition::Enum(enum_) => enum_.name.as_str(),
+            _ => "other",
+        }).collect();
+        assert_eq!(names, vec!["First", "Last"]);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].to_string(), "4:29: error: skipped item, expected Valid identifier");
+        assert_eq!((diagnostics[0].span.start, diagnostics[0].span.end), (51, 113));
+        assert_eq!(diagnostics[1].span.line, 10);
+    }
+
+    #[test]
+    fn parse_modifiers_any_order() {
+        let func = crate::parse_func("native public static quest func Spawn();").unwrap();
+        assert_eq!(func.visibility, Some(Visibility::Public));
+        assert_eq!(func.qualifiers, vec![FuncQualifier::Native, FuncQualifier::Static, FuncQualifier::Quest]);
+
+        let field = crate::parse_field("edit let m_count: Int32;").unwrap();
+        assert_eq!(field.visibility, None);
+        assert_eq!(field.qualifiers, vec![FieldQualifier::Edit]);
+
+        let class = crate::parse_class("importonly class Native {}").unwrap();
+        assert_eq!(class.visibility, None);
+        assert_eq!(class.qualifiers, vec![ClassQualifier::ImportOnly]);
+    }
+
+    #[test]
+    fn parse_modifiers_reported() {
+        let (file, diagnostics) = crate::parse_file_recovering("test.reds", r#"
+public class Foo {
+    static public static func A();
+    public private let m_b: Int32;
+    abstract final func C();
+}
+"#);
+
+        assert_eq!(file.defs.len(), 1);
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "3:19: warning: duplicate `static` qualifier",
+            "4:12: warning: `private` contradicts earlier `public` visibility",
+            "5:25: warning: func `C` is both `abstract` and `final`, but an abstract function has to be overridden",
+        ]);
+    }
+
+    #[test]
+    fn parse_module_imports() {
+        let file = crate::parse_file("test.reds", r#"
+/// Utilities for the inventory
+module Inventory.Utils
+import Codeware.*
+import Inventory.Items.{Item, Stack}
+import Base.Logger
+
+public class Helper {}
+"#).unwrap();
+
+        let module = file.module.as_ref().unwrap();
+        assert_eq!(module.name(), "Inventory.Utils");
+        assert_eq!(module.comments.as_ref().unwrap().lines, vec![Arc::from("Utilities for the inventory")]);
+        assert_eq!(file.qualify("Helper"), "Inventory.Utils.Helper");
+        assert_eq!(file.imports().count(), 3);
+
+        let known = ["Codeware.Json", "Inventory.Items.Stack", "Inventory.Utils.Helper", "Base.Logger", "GameObject"];
+        let resolve = |name| file.resolve(name, |qualified| known.contains(&qualified));
+        assert_eq!(resolve("Helper").as_deref(), Some("Inventory.Utils.Helper"));
+        assert_eq!(resolve("Json").as_deref(), Some("Codeware.Json"));
+        assert_eq!(resolve("Stack").as_deref(), Some("Inventory.Items.Stack"));
+        assert_eq!(resolve("Logger").as_deref(), Some("Base.Logger"));
+        assert_eq!(resolve("GameObject").as_deref(), Some("GameObject"));
+        assert_eq!(resolve("Missing"), None);
+    }
+
+    #[test]
+    fn parse_module_repeated() {
+        let (file, diagnostics) = crate::parse_file_recovering("test.reds", "module A
+module B
+func F();");
+
+        assert_eq!(file.module.unwrap().name(), "A");
+        assert_eq!(file.defs.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "2:1: warning: file already belongs to module `A`");
+    }
+
+    #[test]
+    fn parse_file_recovering_next_item() {
+        let (file, diagnostics) = crate::parse_file_recovering("test.reds", "foo bar\n@addField(A)\npublic class B {}\n");
+
+        assert_eq!(file.defs.len(), 1);
+        match &file.defs[0] {
+            AnyDefinition::Class(class) => {
+                assert_eq!(class.name.as_str(), "B");
+                assert_eq!(class.annotations.len(), 1);
+            }
+            def => panic!("expected a class, got {:?}", def),
+        }
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].span.line, diagnostics[0].span.end), (1, 7));
+    }
+
+    #[test]
+    fn parse_file_recovering_unterminated() {
+        let (file, diagnostics) = crate::parse_file_recovering("test.reds", "enum A { B = 0 }\n;\nclass {");
+
+        assert_eq!(file.defs.len(), 1);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1].span.end, 26);
+    }
+
+    #[test]
+    fn ident_interned() {
+        use std::collections::HashMap;
+
+        let file = crate::parse_file("test.reds", "class B { let m_a: A; } class A { let m_b: B; }").unwrap();
+        let mut classes = HashMap::new();
+        for def in &file.defs {
+            if let AnyDefinition::Class(class) = def {
+                classes.insert(class.name.clone(), class);
+            }
+        }
+
+        // Names parsed from different places are the same identifier
+        let field_type = match &classes["A"].members[0] {
+            MemberDefinition::Field(field) => match &field.type_.type_ {
+                VariableType::Compound(name) => name.clone(),
+                _ => panic!("expected a compound type"),
+            },
+            member => panic!("expected a field, got {:?}", member),
+        };
+        assert_eq!(field_type, Ident::new("B"));
+        assert!(std::ptr::eq(field_type.as_str(), classes[&field_type].name.as_str()));
+
+        let mut names: Vec<Ident> = classes.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec![Ident::new("A"), Ident::new("B")]);
+        assert_ne!(Ident::new("A"), Ident::new("a"));
+
+        // Names still in use are kept when unused ones are released
+        Ident::release_unused();
+        assert!(std::ptr::eq(Ident::new("B").as_str(), field_type.as_str()));
+    }
+
+    #[test]
+    fn parse_thread_safe() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let parsed = std::thread::spawn(|| {
+            crate::parse_file_recovering("test.reds", "/// Doc\n@if(ModuleExists(\"A\")) enum A { B = 0 }")
+        }).join().unwrap();
+
+        assert_send_sync(&parsed);
+        assert_eq!(parsed.0.defs.len(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_json() {
+        let file = crate::parse_file("test.reds", "module A
+/// Doc
+public class B { public let m_c: array<handle<B>>; }").unwrap();
+
+        let json = serde_json::to_value(&file).unwrap();
+        assert_eq!(json["module"]["path"], serde_json::json!(["A"]));
+        assert_eq!(json["defs"][0]["Class"]["name"], "B");
+        assert_eq!(json["defs"][0]["Class"]["comments"]["doc"]["body"], serde_json::json!(["Doc"]));
+        assert_eq!(json["defs"][0]["Class"]["members"][0]["Field"]["type_"]["subtype"]["type_"]["Container"], "Ref");
+
+        let parsed: FileDefinition = serde_json::from_value(json).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", file));
+    }
 }
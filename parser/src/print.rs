@@ -0,0 +1,577 @@
+//! Printing of definitions as canonical redscript, and formatting of whole script files.
+//!
+//! Definitions display as the declaration they were parsed from, with modifiers in a consistent
+//! order and each annotation on its own line. Documentation comments aren't part of the display,
+//! and neither are function bodies, which the AST doesn't keep.
+
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+
+use crate::ast::{
+    Annotation, AnnotationKind, AnyDefinition, ClassDefinition, ClassQualifier, CommentDefinition, EnumDefinition,
+    FieldDefinition, FuncDefinition, ImportDefinition, ImportKind, MemberDefinition, ModuleDefinition, ParamDefinition,
+    ScalarType, Span, VariableType, Visibility
+};
+use crate::cst::{lex, Element, Node, NodeKind, SyntaxTree, Token, TokenKind};
+use crate::source::LineIndex;
+use crate::redscript;
+
+/// Indentation of class members and enum values
+const INDENT: &str = "    ";
+
+/// Writes each annotation on its own line
+fn write_annotations(f: &mut fmt::Formatter<'_>, annotations: &[Annotation]) -> fmt::Result {
+    for annotation in annotations {
+        writeln!(f, "{}", annotation)?;
+    }
+    Ok(())
+}
+
+/// Writes visibility, if it was written, and then qualifiers, each followed by a space
+fn write_modifiers<Q: Display>(
+    f: &mut fmt::Formatter<'_>,
+    visibility: Option<Visibility>,
+    qualifiers: impl IntoIterator<Item = Q>
+) -> fmt::Result {
+    if let Some(visibility) = visibility {
+        write!(f, "{} ", visibility)?;
+    }
+    for qualifier in qualifiers {
+        write!(f, "{} ", qualifier)?;
+    }
+    Ok(())
+}
+
+fn write_joined<T: Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            AnnotationKind::ReplaceGlobal => f.write_str("@replaceGlobal()"),
+            AnnotationKind::ReplaceMethod(class) => write!(f, "@replaceMethod({})", class.as_str()),
+            AnnotationKind::AddMethod(class) => write!(f, "@addMethod({})", class.as_str()),
+            AnnotationKind::AddField(class) => write!(f, "@addField({})", class.as_str()),
+            AnnotationKind::WrapMethod(class) => write!(f, "@wrapMethod({})", class.as_str()),
+            AnnotationKind::If(condition) => write!(f, "@if({})", condition),
+            AnnotationKind::Unsupported(name, args) => {
+                write!(f, "@{}(", name.as_str())?;
+                write_joined(f, args)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Display for ParamDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for qualifier in &self.qualifiers {
+            write!(f, "{} ", qualifier)?;
+        }
+        write!(f, "{}: {}", self.name.as_str(), self.type_)
+    }
+}
+
+/// Functions display as their signature, without a body or terminating `;`. A `Void` return type
+/// is left out.
+impl Display for FuncDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_annotations(f, &self.annotations)?;
+        write_modifiers(f, self.visibility, &self.qualifiers)?;
+        write!(f, "func {}(", self.name.as_str())?;
+        write_joined(f, &self.params)?;
+        f.write_str(")")?;
+        match self.returns.type_ {
+            VariableType::Scalar(ScalarType::Void) => Ok(()),
+            _ => write!(f, " -> {}", self.returns),
+        }
+    }
+}
+
+impl Display for FieldDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_annotations(f, &self.annotations)?;
+        write_modifiers(f, self.visibility, &self.qualifiers)?;
+        write!(f, "let {}: {};", self.name.as_str(), self.type_)
+    }
+}
+
+/// Declaration of a class up to its body, as shown at the top of its page
+pub struct ClassHeader<'a>(pub &'a ClassDefinition);
+
+/// Structs are declared with `struct` in place of `class`
+impl Display for ClassHeader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = self.0;
+        write_annotations(f, &class.annotations)?;
+        let is_struct = class.qualifiers.contains(&ClassQualifier::Struct);
+        write_modifiers(f, class.visibility, class.qualifiers.iter().filter(|q| **q != ClassQualifier::Struct))?;
+        write!(f, "{} {}", if is_struct { "struct" } else { "class" }, class.name.as_str())?;
+        match &class.base {
+            Some(base) => write!(f, " extends {}", base.as_str()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ClassDefinition {
+    pub fn header(&self) -> ClassHeader<'_> {
+        ClassHeader(self)
+    }
+}
+
+/// Classes display with each member on its own line, and functions declared without bodies
+impl Display for ClassDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {{", self.header())?;
+        for member in &self.members {
+            let member = match member {
+                MemberDefinition::Field(field) => field.to_string(),
+                MemberDefinition::Function(func) => format!("{};", func),
+            };
+            for line in member.lines() {
+                write!(f, "\n{}{}", INDENT, line)?;
+            }
+        }
+        f.write_str(if self.members.is_empty() { "}" } else { "\n}" })
+    }
+}
+
+impl Display for EnumDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_annotations(f, &self.annotations)?;
+        write!(f, "enum {} {{", self.name.as_str())?;
+        for value in &self.values {
+            write!(f, "\n{}{} = {},", INDENT, value.name.as_str(), value.value)?;
+        }
+        f.write_str(if self.values.is_empty() { "}" } else { "\n}" })
+    }
+}
+
+impl Display for ModuleDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "module {}", self.name())
+    }
+}
+
+impl Display for ImportDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("import ")?;
+        for (i, part) in self.path.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            f.write_str(part.as_str())?;
+        }
+        match &self.kind {
+            ImportKind::Exact => Ok(()),
+            ImportKind::All => f.write_str(".*"),
+            ImportKind::Selected(names) => {
+                let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                write!(f, ".{{{}}}", names.join(", "))
+            }
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// Formatting
+
+/// Definition or ordinary comment within a block of the formatted output
+struct Entry {
+    /// Byte offset of the start of the entry within the source, including its documentation
+    start: usize,
+    /// Byte offset one past the end of the entry within the source
+    end: usize,
+    /// Formatted text, indented relative to the entry itself
+    text: String,
+    comment: bool,
+}
+
+fn is_plain_comment(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::LineComment | TokenKind::BlockComment)
+}
+
+/// Number of ordinary comments in some text, which formatting must keep
+fn count_comments<'t, 's: 't>(tokens: impl IntoIterator<Item = &'t Token<'s>>) -> usize {
+    tokens.into_iter().filter(|token| is_plain_comment(token.kind)).count()
+}
+
+/// Removes the indentation `base` from each line of `text` after the first, so the text can be
+/// indented again as a whole
+fn dedent(text: &str, base: &str) -> String {
+    let mut lines = text.lines();
+    let mut result = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        result.push('\n');
+        result.push_str(line.strip_prefix(base).unwrap_or(line).trim_end());
+    }
+    result
+}
+
+struct Printer<'a> {
+    source: &'a str,
+}
+
+impl<'a> Printer<'a> {
+    /// Leading whitespace of the line containing `offset`
+    fn base(&self, offset: usize) -> &'a str {
+        let start = self.source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = &self.source[start..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// Source text kept as written, other than its indentation and line endings
+    fn verbatim(&self, start: usize, end: usize) -> String {
+        dedent(&self.source[start..end].replace("\r\n", "\n"), self.base(start))
+    }
+
+    /// End of the source text in `start..end`, before any trailing whitespace
+    fn trimmed_end(&self, start: usize, end: usize) -> usize {
+        start + self.source[start..end].trim_end().len()
+    }
+
+    /// Documentation comment with each line re-indented, followed by a line end
+    fn comments(&self, comments: Option<&CommentDefinition>) -> String {
+        let comments = match comments {
+            Some(comments) => comments,
+            None => return String::new(),
+        };
+
+        let base = self.base(comments.span.start);
+        let mut text = String::new();
+        for line in self.source[comments.span.start..comments.span.end].lines() {
+            let trimmed = line.trim();
+            // Lines of a block comment are aligned under its opening `/**`
+            if trimmed.starts_with('*') {
+                text.push(' ');
+                text.push_str(trimmed);
+            }
+            else if trimmed.starts_with("///") || trimmed.starts_with("/**") {
+                text.push_str(trimmed);
+            }
+            else {
+                text.push_str(line.strip_prefix(base).unwrap_or(line).trim_end());
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Block directly within `container` that forms the body of the function at `span`
+    fn body(&self, container: &'a Node<'a>, span: Span) -> Option<&'a Node<'a>> {
+        container.nodes().find(|node| node.kind == NodeKind::Block && node.start >= span.start && node.end <= span.end)
+    }
+
+    fn func(&self, container: &'a Node<'a>, func: &FuncDefinition) -> String {
+        let body = match self.body(container, func.span) {
+            Some(body) => format!(" {}", self.verbatim(body.start, body.end)),
+            None => ";".to_string(),
+        };
+        format!("{}{}{}", self.comments(func.comments.as_ref()), func, body)
+    }
+
+    fn field(&self, field: &FieldDefinition) -> String {
+        format!("{}{}", self.comments(field.comments.as_ref()), field)
+    }
+
+    /// Entries for the definitions in a block, along with the ordinary comments between them
+    fn entries(&self, block: &Node<'a>, mut entries: Vec<Entry>) -> Vec<Entry> {
+        let mut comments: Vec<Entry> = block.children.iter()
+            .filter_map(|child| match child {
+                Element::Token(token) if is_plain_comment(token.kind) => Some(token),
+                _ => None,
+            })
+            .filter(|token| !entries.iter().any(|entry| (entry.start..entry.end).contains(&token.offset)))
+            .map(|token| Entry {
+                start: token.offset,
+                end: token.offset + token.text.len(),
+                text: self.verbatim(token.offset, token.offset + token.text.len()),
+                comment: true,
+            })
+            .collect();
+
+        entries.append(&mut comments);
+        entries.sort_by_key(|entry| entry.start);
+        entries
+    }
+
+    /// Writes entries one per line with the given indent. Single blank lines between entries are
+    /// kept, as are comments that trail an entry on the same line.
+    fn write_entries(&self, out: &mut String, entries: &[Entry], indent: &str) {
+        let mut prev_end: Option<usize> = None;
+        for entry in entries {
+            let gap = prev_end.map(|prev_end| &self.source[prev_end..entry.start]);
+            let trailing = entry.comment && gap.is_some_and(|gap| !gap.contains('\n'));
+            if trailing {
+                out.push(' ');
+            }
+            else if let Some(gap) = gap {
+                out.push_str(if gap.matches('\n').count() > 1 { "\n\n" } else { "\n" });
+            }
+
+            for (i, line) in entry.text.lines().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                // The first line of a trailing comment follows on from the previous entry
+                let continues = trailing && i == 0;
+                if !line.is_empty() && !continues {
+                    out.push_str(indent);
+                }
+                out.push_str(line);
+            }
+            prev_end = Some(entry.end);
+        }
+    }
+
+    /// Writes `header {`, the indented entries and `}`, or just `header {}` if there are none
+    fn block(&self, header: String, entries: &[Entry]) -> String {
+        if entries.is_empty() {
+            return format!("{} {{}}", header);
+        }
+        let mut text = format!("{} {{\n", header);
+        self.write_entries(&mut text, entries, INDENT);
+        text.push_str("\n}");
+        text
+    }
+
+    fn class(&self, node: &'a Node<'a>, class: &ClassDefinition) -> String {
+        let header = format!("{}{}", self.comments(class.comments.as_ref()), class.header());
+        let block = match self.body(node, class.span) {
+            Some(block) => block,
+            None => return self.block(header, &[]),
+        };
+
+        let members = class.members.iter()
+            .map(|member| match member {
+                MemberDefinition::Field(field) => (&field.comments, field.span, self.field(field)),
+                MemberDefinition::Function(func) => (&func.comments, func.span, self.func(block, func)),
+            })
+            .map(|(comments, span, text)| Entry {
+                start: comments.as_ref().map_or(span.start, |comments| comments.span.start),
+                end: span.end,
+                text,
+                comment: false,
+            })
+            .collect();
+        self.block(header, &self.entries(block, members))
+    }
+
+    fn enum_(&self, node: &'a Node<'a>, enum_: &EnumDefinition) -> String {
+        let mut header = self.comments(enum_.comments.as_ref());
+        for annotation in &enum_.annotations {
+            header.push_str(&format!("{}\n", annotation));
+        }
+        header.push_str(&format!("enum {}", enum_.name.as_str()));
+        let block = match self.body(node, enum_.span) {
+            Some(block) => block,
+            None => return self.block(header, &[]),
+        };
+
+        let values = enum_.values.iter()
+            .map(|value| {
+                // Values are kept as written, so hexadecimal bitmasks stay readable
+                let text = &self.source[value.span.start..value.span.end];
+                let literal = text[text.find('=').map_or(0, |i| i + 1)..].trim();
+                Entry {
+                    start: value.comments.as_ref().map_or(value.span.start, |comments| comments.span.start),
+                    end: value.span.end,
+                    text: format!("{}{} = {},", self.comments(value.comments.as_ref()), value.name.as_str(), literal),
+                    comment: false,
+                }
+            })
+            .collect();
+        self.block(header, &self.entries(block, values))
+    }
+
+    fn item(&self, node: &'a Node<'a>, def: &AnyDefinition) -> Option<String> {
+        let text = match def {
+            AnyDefinition::Module(module) => format!("{}{}", self.comments(module.comments.as_ref()), module),
+            AnyDefinition::Import(import) => import.to_string(),
+            AnyDefinition::Enum(enum_) => self.enum_(node, enum_),
+            AnyDefinition::Class(class) => self.class(node, class),
+            AnyDefinition::Func(func) => self.func(node, func),
+            AnyDefinition::Field(field) => self.field(field),
+            AnyDefinition::Comments(comments) => self.comments(Some(comments)),
+            _ => return None,
+        };
+        Some(text.trim_end().to_string())
+    }
+}
+
+/// Formats a script file as canonical redscript. Declarations are printed the same way as they
+/// display, while function bodies, items that can't be parsed and items with a repeated modifier
+/// or a second visibility are kept as written. Ordinary comments between definitions and single
+/// blank lines are kept, and a byte order mark and CRLF line endings are kept if the source has
+/// them.
+pub fn format(source: &str) -> String {
+    let tree = SyntaxTree::parse(source);
+    let index = LineIndex::new(source);
+    let printer = Printer { source };
+
+    let mut entries = Vec::new();
+    for node in tree.root.nodes() {
+        let end = printer.trimmed_end(node.start, node.end);
+        let reported = RefCell::default();
+        let def = match node.kind {
+            NodeKind::Skipped => None,
+            _ => redscript::definition_at(source, &index, &reported, &(node.start..node.end)).ok(),
+        };
+        // Items with warnings, such as a repeated modifier that the definition leaves out, and
+        // items whose comments can't all be placed, such as one within a signature, are kept as
+        // written rather than lose any
+        let text = def.filter(|_| reported.borrow().is_empty())
+            .and_then(|def| printer.item(node, &def))
+            .filter(|text| count_comments(&lex(text)) == count_comments(node.tokens()))
+            .unwrap_or_else(|| printer.verbatim(node.start, end));
+        entries.push(Entry { start: node.start, end, text, comment: false });
+    }
+
+    let mut text = String::new();
+    printer.write_entries(&mut text, &printer.entries(&tree.root, entries), "");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+
+    if source.contains("\r\n") {
+        text = text.replace('\n', "\r\n");
+    }
+    if source.starts_with('\u{FEFF}') {
+        text.insert(0, '\u{FEFF}');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{AnyDefinition, ContainerType, TypeDeclaration, VariableType};
+
+    #[test]
+    fn display_definitions() {
+        let file = crate::parse_file("test.reds", r#"
+@if(ModuleExists("Other"))
+abstract public class Vehicle extends Entity {
+    persistent public let m_speed: handle<Speed>;
+    @wrapMethod(Vehicle)
+    final func Drive(out distance: Float, opt fast: Bool) -> [Int32; 4] { return; }
+    native func Stop() -> Void;
+}
+
+struct Wheel {}
+
+enum Gear { Low = 0x1, High = -2 }
+"#).unwrap();
+
+        let printed: Vec<String> = file.defs.iter()
+            .map(|def| match def {
+                AnyDefinition::Class(class) => class.to_string(),
+                AnyDefinition::Enum(enum_) => enum_.to_string(),
+                def => panic!("unexpected definition {:?}", def),
+            })
+            .collect();
+
+        assert_eq!(printed, vec![
+"@if(ModuleExists(\"Other\"))
+public abstract class Vehicle extends Entity {
+    public persistent let m_speed: ref<Speed>;
+    @wrapMethod(Vehicle)
+    final func Drive(out distance: Float, opt fast: Bool) -> [Int32; 4];
+    native func Stop();
+}",
+"struct Wheel {}",
+"enum Gear {
+    Low = 1,
+    High = -2,
+}",
+        ]);
+
+        // A container without a subtype can't be parsed, but can still be printed
+        let type_ = TypeDeclaration::leaf(VariableType::Container(ContainerType::Array));
+        assert_eq!(type_.to_string(), "array<?>");
+    }
+
+    #[test]
+    fn format_file() {
+        let source = "module   Vehicles
+import Base.{Entity,   Speed}
+// Cars and such
+
+
+/// A vehicle
+public   abstract class Car extends Entity{
+    native   public let m_speed : handle<Speed>; // km/h
+    /**
+      * Drives
+      */
+    public func Drive()   -> Void {
+        if true { Log(\"}\"); } // keep
+    }
+
+    // Never used
+    func Stop( ) ;
+}
+enum Gear { Low = 0x1 , /// Fast
+  High = 2 }
+public class Broken extends {
+}
+";
+
+        assert_eq!(format(source), "module Vehicles
+import Base.{Entity, Speed}
+// Cars and such
+
+/// A vehicle
+public abstract class Car extends Entity {
+    public native let m_speed: ref<Speed>; // km/h
+    /**
+     * Drives
+     */
+    public func Drive() {
+        if true { Log(\"}\"); } // keep
+    }
+
+    // Never used
+    func Stop();
+}
+enum Gear {
+    Low = 0x1,
+    /// Fast
+    High = 2,
+}
+public class Broken extends {
+}
+");
+    }
+
+    #[test]
+    fn format_stable() {
+        let sources = [
+            "/// Doc\r\nenum A { B = 0 }\r\n\r\n\r\nfunc F() -> Bool { return true; }\r\n",
+            "\u{FEFF}class C { let m_a: Int32; /* note */ let m_b: Int32; }\n",
+            // Comments within a signature can't be placed, so the class is kept as written
+            "class C {\n  func F(/* none */) {}\n}\n",
+            // The second visibility and the repeated qualifier aren't in the definitions
+            "class C {\n  public private let m_a: Int32;\n  static public static func A();\n}\n",
+        ];
+
+        for source in &sources {
+            let formatted = format(source);
+            assert_eq!(format(&formatted), formatted);
+            assert_eq!(formatted.contains("\r\n"), source.contains("\r\n"));
+            assert_eq!(formatted.starts_with('\u{FEFF}'), source.starts_with('\u{FEFF}'));
+        }
+        assert_eq!(format(sources[2]), sources[2]);
+        assert_eq!(format(sources[3]), sources[3]);
+        assert_eq!(format("abstract  final func C();\n"), "abstract final func C();\n");
+        assert_eq!(format(sources[1]), "\u{FEFF}class C {\n    let m_a: Int32; /* note */\n    let m_b: Int32;\n}\n");
+    }
+}
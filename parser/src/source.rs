@@ -0,0 +1,281 @@
+use std::{error, fmt};
+
+use crate::ast::{
+    Annotation, ClassDefinition, CommentDefinition, EnumDefinition, EnumValDefinition, Expression, FieldDefinition,
+    FileDefinition, FuncDefinition, ImportDefinition, ModuleDefinition, ParamDefinition, Span, Statement,
+    StatementKind, TypeDeclaration
+};
+use crate::diagnostic::Diagnostic;
+use crate::visit::{
+    walk_class_mut, walk_enum_mut, walk_enum_value_mut, walk_expression_mut, walk_field_mut, walk_func_mut,
+    walk_module_mut, walk_param_mut, walk_statement_mut, walk_type_mut, VisitorMut
+};
+
+/// Error decoding the bytes of a script file into text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub encoding: &'static str,
+    /// Byte offset of the first invalid data
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} at byte {}", self.encoding, self.offset)
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// Text of a script file, along with what's needed to map offsets within it back to the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub text: String,
+    /// For UTF-16 files, the offsets within the text and within the file after the byte order mark
+    /// and after each non-ASCII character. ASCII characters in between take one byte in the text
+    /// and two in the file.
+    utf16: Option<Vec<(usize, usize)>>,
+}
+
+impl Decoded {
+    /// Byte offset within the file of a byte offset within the text
+    pub fn file_offset(&self, offset: usize) -> usize {
+        match &self.utf16 {
+            None => offset,
+            Some(offsets) => {
+                let (text, file) = offsets[offsets.partition_point(|(text, _)| *text <= offset) - 1];
+                file + (offset - text) * 2
+            }
+        }
+    }
+
+    /// Maps the spans of definitions parsed from the text, and of their diagnostics, to byte
+    /// offsets within the file. Only spans into UTF-16 files change, as their lines and columns
+    /// already match the file.
+    pub fn map_spans(&self, file: &mut FileDefinition, diagnostics: &mut [Diagnostic]) {
+        if self.utf16.is_none() {
+            return;
+        }
+        let mut mapper = SpanMapper(self);
+        mapper.visit_file_mut(file);
+        for diagnostic in diagnostics {
+            mapper.map(&mut diagnostic.span);
+        }
+    }
+}
+
+/// Maps every span of the AST to offsets within the file
+struct SpanMapper<'a>(&'a Decoded);
+
+impl SpanMapper<'_> {
+    fn map(&self, span: &mut Span) {
+        span.start = self.0.file_offset(span.start);
+        span.end = self.0.file_offset(span.end);
+    }
+}
+
+impl VisitorMut for SpanMapper<'_> {
+    fn visit_comments_mut(&mut self, comments: &mut CommentDefinition) {
+        self.map(&mut comments.span);
+    }
+    fn visit_module_mut(&mut self, module: &mut ModuleDefinition) {
+        self.map(&mut module.span);
+        walk_module_mut(self, module);
+    }
+    fn visit_import_mut(&mut self, import: &mut ImportDefinition) {
+        self.map(&mut import.span);
+    }
+    fn visit_annotation_mut(&mut self, annotation: &mut Annotation) {
+        if let Some(ident_span) = &mut annotation.ident_span {
+            self.map(ident_span);
+        }
+        self.map(&mut annotation.span);
+    }
+    fn visit_enum_mut(&mut self, enum_: &mut EnumDefinition) {
+        self.map(&mut enum_.name_span);
+        self.map(&mut enum_.span);
+        walk_enum_mut(self, enum_);
+    }
+    fn visit_enum_value_mut(&mut self, value: &mut EnumValDefinition) {
+        self.map(&mut value.name_span);
+        self.map(&mut value.span);
+        walk_enum_value_mut(self, value);
+    }
+    fn visit_class_mut(&mut self, class: &mut ClassDefinition) {
+        self.map(&mut class.name_span);
+        if let Some(base_span) = &mut class.base_span {
+            self.map(base_span);
+        }
+        self.map(&mut class.span);
+        walk_class_mut(self, class);
+    }
+    fn visit_func_mut(&mut self, func: &mut FuncDefinition) {
+        self.map(&mut func.name_span);
+        if let Some(body_span) = &mut func.body_span {
+            self.map(body_span);
+        }
+        self.map(&mut func.span);
+        walk_func_mut(self, func);
+    }
+    fn visit_param_mut(&mut self, param: &mut ParamDefinition) {
+        self.map(&mut param.name_span);
+        self.map(&mut param.span);
+        walk_param_mut(self, param);
+    }
+    fn visit_field_mut(&mut self, field: &mut FieldDefinition) {
+        self.map(&mut field.name_span);
+        self.map(&mut field.span);
+        walk_field_mut(self, field);
+    }
+    fn visit_type_mut(&mut self, type_: &mut TypeDeclaration) {
+        if let Some(span) = &mut type_.span {
+            self.map(span);
+        }
+        walk_type_mut(self, type_);
+    }
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        self.map(&mut statement.span);
+        if let StatementKind::Switch { cases, .. } = &mut statement.kind {
+            for case in cases {
+                self.map(&mut case.span);
+            }
+        }
+        walk_statement_mut(self, statement);
+    }
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        self.map(&mut expr.span);
+        walk_expression_mut(self, expr);
+    }
+}
+
+/// Decodes the bytes of a script file into text that the parser accepts. Files are expected to be
+/// UTF-8, with or without a byte order mark, which is kept so that spans remain byte offsets into
+/// the file. UTF-16 files with a byte order mark are also accepted, in which case spans are offsets
+/// into the decoded text until they're mapped back to the file with [`Decoded::map_spans`],
+/// although their lines and columns already match the file.
+pub fn decode(bytes: &[u8]) -> Result<Decoded, DecodeError> {
+    let (units, big_endian) = match bytes {
+        [0xFF, 0xFE, rest @ ..] => (rest, false),
+        [0xFE, 0xFF, rest @ ..] => (rest, true),
+        _ => {
+            return String::from_utf8(bytes.to_vec())
+                .map(|text| Decoded { text, utf16: None })
+                .map_err(|err| DecodeError { encoding: "UTF-8", offset: err.utf8_error().valid_up_to() });
+        }
+    };
+
+    let mut text = String::with_capacity(units.len() / 2);
+    let chunks = units.chunks(2).map(|pair| match pair {
+        [a, b] if big_endian => Ok(u16::from_be_bytes([*a, *b])),
+        [a, b] => Ok(u16::from_le_bytes([*a, *b])),
+        _ => Err(()),
+    });
+    // Offsets count the byte order mark
+    let mut offset = 2;
+    let mut offsets = vec![(0, offset)];
+    for c in std::char::decode_utf16(chunks.map_while(Result::ok)) {
+        match c {
+            Ok(c) => {
+                offset += c.len_utf16() * 2;
+                text.push(c);
+                if !c.is_ascii() {
+                    offsets.push((text.len(), offset));
+                }
+            }
+            Err(_) => return Err(DecodeError { encoding: "UTF-16", offset }),
+        }
+    }
+    if units.len() % 2 != 0 {
+        return Err(DecodeError { encoding: "UTF-16", offset });
+    }
+
+    Ok(Decoded { text, utf16: Some(offsets) })
+}
+
+/// Lookup table from byte offsets to line and column numbers within a source text
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        LineIndex { text, line_starts }
+    }
+
+    /// 1-based line and column of a byte offset. Columns count characters rather than bytes, to
+    /// match the locations reported by `ParseError`, and a byte order mark isn't counted.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let mut start = self.line_starts[line];
+        if start == 0 && self.text.starts_with('\u{FEFF}') {
+            start = offset.min('\u{FEFF}'.len_utf8());
+        }
+        let column = self.text[start..offset].chars().count() + 1;
+
+        (line + 1, column)
+    }
+
+    /// Span covering the byte range `start..end`, located by its first character
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let (line, column) = self.line_col(start);
+        Span { start, end, line, column }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_utf16() {
+        let mut le = vec![0xFF, 0xFE];
+        let mut be = vec![0xFE, 0xFF];
+        for unit in "enum Ä {}\r\n".encode_utf16() {
+            le.extend_from_slice(&unit.to_le_bytes());
+            be.extend_from_slice(&unit.to_be_bytes());
+        }
+
+        assert_eq!(decode(&le).unwrap().text, "enum Ä {}\r\n");
+        assert_eq!(decode(&be).unwrap().text, "enum Ä {}\r\n");
+
+        le.pop();
+        assert_eq!(decode(&le), Err(DecodeError { encoding: "UTF-16", offset: 22 }));
+        // An unpaired surrogate
+        assert_eq!(decode(&[0xFF, 0xFE, 0x41, 0x00, 0x00, 0xD8]), Err(DecodeError { encoding: "UTF-16", offset: 4 }));
+    }
+
+    #[test]
+    fn decode_utf8() {
+        assert_eq!(decode(b"\xEF\xBB\xBFenum").unwrap().text, "\u{FEFF}enum");
+        assert_eq!(decode(b"enum \xFF"), Err(DecodeError { encoding: "UTF-8", offset: 5 }));
+    }
+
+    #[test]
+    fn map_utf16_spans() {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in "/// Ä\nenum A { B = 0 }\nclass {".encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        let source = decode(&bytes).unwrap();
+        let (mut file, mut diagnostics) = crate::parse_file_recovering("test.reds", &source.text);
+        source.map_spans(&mut file, &mut diagnostics);
+
+        let enum_ = match &file.defs[..] {
+            [crate::ast::AnyDefinition::Enum(enum_)] => enum_,
+            defs => panic!("unexpected definitions {:?}", defs),
+        };
+        // Two bytes per character after the byte order mark
+        assert_eq!(enum_.comments.as_ref().unwrap().span, Span::new(2, 12, 1, 1));
+        assert_eq!(enum_.span, Span::new(14, 46, 2, 1));
+        assert_eq!(enum_.name_span, Span::new(24, 26, 2, 6));
+        assert_eq!(diagnostics[0].span, Span::new(60, 62, 3, 7));
+    }
+}
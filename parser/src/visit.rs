@@ -0,0 +1,552 @@
+//! Traversal of the AST. Implementations of [`Visitor`] and [`VisitorMut`] override the methods
+//! for the nodes they're interested in, and call the matching `walk_` function from an override to
+//! carry on into its children.
+
+use crate::ast::{
+    Annotation, AnyDefinition, ClassDefinition, CommentDefinition, EnumDefinition, EnumValDefinition, Expression,
+    ExpressionKind, FieldDefinition, FileDefinition, FuncDefinition, ImportDefinition, MemberDefinition,
+    ModuleDefinition, ParamDefinition, Statement, StatementKind, TypeDeclaration
+};
+
+/// Visits the nodes of the AST in source order. Every method walks into the children of its node
+/// unless overridden.
+pub trait Visitor {
+    fn visit_file(&mut self, file: &FileDefinition) {
+        walk_file(self, file)
+    }
+    fn visit_definition(&mut self, def: &AnyDefinition) {
+        walk_definition(self, def)
+    }
+    /// Documentation of a definition, or free-standing comments
+    fn visit_comments(&mut self, _comments: &CommentDefinition) {}
+    fn visit_module(&mut self, module: &ModuleDefinition) {
+        walk_module(self, module)
+    }
+    fn visit_import(&mut self, _import: &ImportDefinition) {}
+    fn visit_annotation(&mut self, _annotation: &Annotation) {}
+    fn visit_enum(&mut self, enum_: &EnumDefinition) {
+        walk_enum(self, enum_)
+    }
+    fn visit_enum_value(&mut self, value: &EnumValDefinition) {
+        walk_enum_value(self, value)
+    }
+    fn visit_class(&mut self, class: &ClassDefinition) {
+        walk_class(self, class)
+    }
+    fn visit_member(&mut self, member: &MemberDefinition) {
+        walk_member(self, member)
+    }
+    fn visit_func(&mut self, func: &FuncDefinition) {
+        walk_func(self, func)
+    }
+    fn visit_param(&mut self, param: &ParamDefinition) {
+        walk_param(self, param)
+    }
+    fn visit_field(&mut self, field: &FieldDefinition) {
+        walk_field(self, field)
+    }
+    /// Type of a field, parameter, return value, local variable, cast or type argument, and then
+    /// each of its nested subtypes
+    fn visit_type(&mut self, type_: &TypeDeclaration) {
+        walk_type(self, type_)
+    }
+    /// Statement of a function body, which is only visited once bodies have been parsed
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement)
+    }
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+}
+
+fn visit_comments<V: Visitor + ?Sized>(visitor: &mut V, comments: &Option<CommentDefinition>) {
+    if let Some(comments) = comments {
+        visitor.visit_comments(comments);
+    }
+}
+
+fn visit_annotations<V: Visitor + ?Sized>(visitor: &mut V, annotations: &[Annotation]) {
+    for annotation in annotations {
+        visitor.visit_annotation(annotation);
+    }
+}
+
+pub fn walk_file<V: Visitor + ?Sized>(visitor: &mut V, file: &FileDefinition) {
+    visit_comments(visitor, &file.comments);
+    if let Some(module) = &file.module {
+        visitor.visit_module(module);
+    }
+    for def in &file.defs {
+        visitor.visit_definition(def);
+    }
+}
+
+pub fn walk_definition<V: Visitor + ?Sized>(visitor: &mut V, def: &AnyDefinition) {
+    match def {
+        AnyDefinition::Comments(comments) => visitor.visit_comments(comments),
+        AnyDefinition::Enum(enum_) => visitor.visit_enum(enum_),
+        AnyDefinition::EnumVal(value) => visitor.visit_enum_value(value),
+        AnyDefinition::Class(class) => visitor.visit_class(class),
+        AnyDefinition::Func(func) => visitor.visit_func(func),
+        AnyDefinition::Param(param) => visitor.visit_param(param),
+        AnyDefinition::Field(field) => visitor.visit_field(field),
+        AnyDefinition::File(file) => visitor.visit_file(file),
+        AnyDefinition::Module(module) => visitor.visit_module(module),
+        AnyDefinition::Import(import) => visitor.visit_import(import),
+    }
+}
+
+pub fn walk_module<V: Visitor + ?Sized>(visitor: &mut V, module: &ModuleDefinition) {
+    visit_comments(visitor, &module.comments);
+}
+
+pub fn walk_enum<V: Visitor + ?Sized>(visitor: &mut V, enum_: &EnumDefinition) {
+    visit_comments(visitor, &enum_.comments);
+    visit_annotations(visitor, &enum_.annotations);
+    for value in &enum_.values {
+        visitor.visit_enum_value(value);
+    }
+}
+
+pub fn walk_enum_value<V: Visitor + ?Sized>(visitor: &mut V, value: &EnumValDefinition) {
+    visit_comments(visitor, &value.comments);
+}
+
+pub fn walk_class<V: Visitor + ?Sized>(visitor: &mut V, class: &ClassDefinition) {
+    visit_comments(visitor, &class.comments);
+    visit_annotations(visitor, &class.annotations);
+    for member in &class.members {
+        visitor.visit_member(member);
+    }
+}
+
+pub fn walk_member<V: Visitor + ?Sized>(visitor: &mut V, member: &MemberDefinition) {
+    match member {
+        MemberDefinition::Field(field) => visitor.visit_field(field),
+        MemberDefinition::Function(func) => visitor.visit_func(func),
+    }
+}
+
+pub fn walk_func<V: Visitor + ?Sized>(visitor: &mut V, func: &FuncDefinition) {
+    visit_comments(visitor, &func.comments);
+    visit_annotations(visitor, &func.annotations);
+    for param in &func.params {
+        visitor.visit_param(param);
+    }
+    visitor.visit_type(&func.returns);
+    if let Some(body) = &func.body {
+        visit_statements(visitor, body);
+    }
+}
+
+pub fn walk_param<V: Visitor + ?Sized>(visitor: &mut V, param: &ParamDefinition) {
+    visitor.visit_type(&param.type_);
+}
+
+pub fn walk_field<V: Visitor + ?Sized>(visitor: &mut V, field: &FieldDefinition) {
+    visit_comments(visitor, &field.comments);
+    visit_annotations(visitor, &field.annotations);
+    visitor.visit_type(&field.type_);
+}
+
+pub fn walk_type<V: Visitor + ?Sized>(visitor: &mut V, type_: &TypeDeclaration) {
+    if let Some(subtype) = &type_.subtype {
+        visitor.visit_type(subtype);
+    }
+}
+
+fn visit_statements<V: Visitor + ?Sized>(visitor: &mut V, statements: &[Statement]) {
+    for statement in statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match &statement.kind {
+        StatementKind::Let { type_, value, .. } => {
+            if let Some(type_) = type_ {
+                visitor.visit_type(type_);
+            }
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        StatementKind::Expression(expr) | StatementKind::Return(Some(expr)) => visitor.visit_expression(expr),
+        StatementKind::Return(None) | StatementKind::Break | StatementKind::Continue => {}
+        StatementKind::If { condition, then, else_ } => {
+            visitor.visit_expression(condition);
+            visit_statements(visitor, then);
+            if let Some(else_) = else_ {
+                visit_statements(visitor, else_);
+            }
+        }
+        StatementKind::Switch { value, cases } => {
+            visitor.visit_expression(value);
+            for case in cases {
+                if let Some(label) = &case.label {
+                    visitor.visit_expression(label);
+                }
+                visit_statements(visitor, &case.body);
+            }
+        }
+        StatementKind::While { condition: expr, body } | StatementKind::For { array: expr, body, .. } => {
+            visitor.visit_expression(expr);
+            visit_statements(visitor, body);
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    match &expr.kind {
+        ExpressionKind::Ident(_)
+        | ExpressionKind::Constant(_)
+        | ExpressionKind::This
+        | ExpressionKind::Super
+        | ExpressionKind::Null => {}
+        ExpressionKind::Array(items) | ExpressionKind::New(_, items) => {
+            for item in items {
+                visitor.visit_expression(item);
+            }
+        }
+        ExpressionKind::Member(x, _) | ExpressionKind::Unary(_, x) => visitor.visit_expression(x),
+        ExpressionKind::Index(x, y) | ExpressionKind::Binary(_, x, y) | ExpressionKind::Assign(_, x, y) => {
+            visitor.visit_expression(x);
+            visitor.visit_expression(y);
+        }
+        ExpressionKind::Call { callee, type_args, args } => {
+            visitor.visit_expression(callee);
+            for type_ in type_args {
+                visitor.visit_type(type_);
+            }
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+        ExpressionKind::Cast(x, type_) => {
+            visitor.visit_expression(x);
+            visitor.visit_type(type_);
+        }
+        ExpressionKind::Conditional(x, y, z) => {
+            visitor.visit_expression(x);
+            visitor.visit_expression(y);
+            visitor.visit_expression(z);
+        }
+    }
+}
+
+/// Visits the nodes of the AST in source order, with the chance to change them. Every method walks
+/// into the children of its node unless overridden.
+pub trait VisitorMut {
+    fn visit_file_mut(&mut self, file: &mut FileDefinition) {
+        walk_file_mut(self, file)
+    }
+    fn visit_definition_mut(&mut self, def: &mut AnyDefinition) {
+        walk_definition_mut(self, def)
+    }
+    /// Documentation of a definition, or free-standing comments
+    fn visit_comments_mut(&mut self, _comments: &mut CommentDefinition) {}
+    fn visit_module_mut(&mut self, module: &mut ModuleDefinition) {
+        walk_module_mut(self, module)
+    }
+    fn visit_import_mut(&mut self, _import: &mut ImportDefinition) {}
+    fn visit_annotation_mut(&mut self, _annotation: &mut Annotation) {}
+    fn visit_enum_mut(&mut self, enum_: &mut EnumDefinition) {
+        walk_enum_mut(self, enum_)
+    }
+    fn visit_enum_value_mut(&mut self, value: &mut EnumValDefinition) {
+        walk_enum_value_mut(self, value)
+    }
+    fn visit_class_mut(&mut self, class: &mut ClassDefinition) {
+        walk_class_mut(self, class)
+    }
+    fn visit_member_mut(&mut self, member: &mut MemberDefinition) {
+        walk_member_mut(self, member)
+    }
+    fn visit_func_mut(&mut self, func: &mut FuncDefinition) {
+        walk_func_mut(self, func)
+    }
+    fn visit_param_mut(&mut self, param: &mut ParamDefinition) {
+        walk_param_mut(self, param)
+    }
+    fn visit_field_mut(&mut self, field: &mut FieldDefinition) {
+        walk_field_mut(self, field)
+    }
+    /// Type of a field, parameter, return value, local variable, cast or type argument, and then
+    /// each of its nested subtypes
+    fn visit_type_mut(&mut self, type_: &mut TypeDeclaration) {
+        walk_type_mut(self, type_)
+    }
+    /// Statement of a function body, which is only visited once bodies have been parsed
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement)
+    }
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+}
+
+fn visit_comments_mut<V: VisitorMut + ?Sized>(visitor: &mut V, comments: &mut Option<CommentDefinition>) {
+    if let Some(comments) = comments {
+        visitor.visit_comments_mut(comments);
+    }
+}
+
+fn visit_annotations_mut<V: VisitorMut + ?Sized>(visitor: &mut V, annotations: &mut [Annotation]) {
+    for annotation in annotations {
+        visitor.visit_annotation_mut(annotation);
+    }
+}
+
+pub fn walk_file_mut<V: VisitorMut + ?Sized>(visitor: &mut V, file: &mut FileDefinition) {
+    visit_comments_mut(visitor, &mut file.comments);
+    if let Some(module) = &mut file.module {
+        visitor.visit_module_mut(module);
+    }
+    for def in &mut file.defs {
+        visitor.visit_definition_mut(def);
+    }
+}
+
+pub fn walk_definition_mut<V: VisitorMut + ?Sized>(visitor: &mut V, def: &mut AnyDefinition) {
+    match def {
+        AnyDefinition::Comments(comments) => visitor.visit_comments_mut(comments),
+        AnyDefinition::Enum(enum_) => visitor.visit_enum_mut(enum_),
+        AnyDefinition::EnumVal(value) => visitor.visit_enum_value_mut(value),
+        AnyDefinition::Class(class) => visitor.visit_class_mut(class),
+        AnyDefinition::Func(func) => visitor.visit_func_mut(func),
+        AnyDefinition::Param(param) => visitor.visit_param_mut(param),
+        AnyDefinition::Field(field) => visitor.visit_field_mut(field),
+        AnyDefinition::File(file) => visitor.visit_file_mut(file),
+        AnyDefinition::Module(module) => visitor.visit_module_mut(module),
+        AnyDefinition::Import(import) => visitor.visit_import_mut(import),
+    }
+}
+
+pub fn walk_module_mut<V: VisitorMut + ?Sized>(visitor: &mut V, module: &mut ModuleDefinition) {
+    visit_comments_mut(visitor, &mut module.comments);
+}
+
+pub fn walk_enum_mut<V: VisitorMut + ?Sized>(visitor: &mut V, enum_: &mut EnumDefinition) {
+    visit_comments_mut(visitor, &mut enum_.comments);
+    visit_annotations_mut(visitor, &mut enum_.annotations);
+    for value in &mut enum_.values {
+        visitor.visit_enum_value_mut(value);
+    }
+}
+
+pub fn walk_enum_value_mut<V: VisitorMut + ?Sized>(visitor: &mut V, value: &mut EnumValDefinition) {
+    visit_comments_mut(visitor, &mut value.comments);
+}
+
+pub fn walk_class_mut<V: VisitorMut + ?Sized>(visitor: &mut V, class: &mut ClassDefinition) {
+    visit_comments_mut(visitor, &mut class.comments);
+    visit_annotations_mut(visitor, &mut class.annotations);
+    for member in &mut class.members {
+        visitor.visit_member_mut(member);
+    }
+}
+
+pub fn walk_member_mut<V: VisitorMut + ?Sized>(visitor: &mut V, member: &mut MemberDefinition) {
+    match member {
+        MemberDefinition::Field(field) => visitor.visit_field_mut(field),
+        MemberDefinition::Function(func) => visitor.visit_func_mut(func),
+    }
+}
+
+pub fn walk_func_mut<V: VisitorMut + ?Sized>(visitor: &mut V, func: &mut FuncDefinition) {
+    visit_comments_mut(visitor, &mut func.comments);
+    visit_annotations_mut(visitor, &mut func.annotations);
+    for param in &mut func.params {
+        visitor.visit_param_mut(param);
+    }
+    visitor.visit_type_mut(&mut func.returns);
+    if let Some(body) = &mut func.body {
+        visit_statements_mut(visitor, body);
+    }
+}
+
+pub fn walk_param_mut<V: VisitorMut + ?Sized>(visitor: &mut V, param: &mut ParamDefinition) {
+    visitor.visit_type_mut(&mut param.type_);
+}
+
+pub fn walk_field_mut<V: VisitorMut + ?Sized>(visitor: &mut V, field: &mut FieldDefinition) {
+    visit_comments_mut(visitor, &mut field.comments);
+    visit_annotations_mut(visitor, &mut field.annotations);
+    visitor.visit_type_mut(&mut field.type_);
+}
+
+pub fn walk_type_mut<V: VisitorMut + ?Sized>(visitor: &mut V, type_: &mut TypeDeclaration) {
+    if let Some(subtype) = &mut type_.subtype {
+        visitor.visit_type_mut(subtype);
+    }
+}
+
+fn visit_statements_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statements: &mut [Statement]) {
+    for statement in statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::Let { type_, value, .. } => {
+            if let Some(type_) = type_ {
+                visitor.visit_type_mut(type_);
+            }
+            if let Some(value) = value {
+                visitor.visit_expression_mut(value);
+            }
+        }
+        StatementKind::Expression(expr) | StatementKind::Return(Some(expr)) => visitor.visit_expression_mut(expr),
+        StatementKind::Return(None) | StatementKind::Break | StatementKind::Continue => {}
+        StatementKind::If { condition, then, else_ } => {
+            visitor.visit_expression_mut(condition);
+            visit_statements_mut(visitor, then);
+            if let Some(else_) = else_ {
+                visit_statements_mut(visitor, else_);
+            }
+        }
+        StatementKind::Switch { value, cases } => {
+            visitor.visit_expression_mut(value);
+            for case in cases {
+                if let Some(label) = &mut case.label {
+                    visitor.visit_expression_mut(label);
+                }
+                visit_statements_mut(visitor, &mut case.body);
+            }
+        }
+        StatementKind::While { condition: expr, body } | StatementKind::For { array: expr, body, .. } => {
+            visitor.visit_expression_mut(expr);
+            visit_statements_mut(visitor, body);
+        }
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expression) {
+    match &mut expr.kind {
+        ExpressionKind::Ident(_)
+        | ExpressionKind::Constant(_)
+        | ExpressionKind::This
+        | ExpressionKind::Super
+        | ExpressionKind::Null => {}
+        ExpressionKind::Array(items) | ExpressionKind::New(_, items) => {
+            for item in items {
+                visitor.visit_expression_mut(item);
+            }
+        }
+        ExpressionKind::Member(x, _) | ExpressionKind::Unary(_, x) => visitor.visit_expression_mut(x),
+        ExpressionKind::Index(x, y) | ExpressionKind::Binary(_, x, y) | ExpressionKind::Assign(_, x, y) => {
+            visitor.visit_expression_mut(x);
+            visitor.visit_expression_mut(y);
+        }
+        ExpressionKind::Call { callee, type_args, args } => {
+            visitor.visit_expression_mut(callee);
+            for type_ in type_args {
+                visitor.visit_type_mut(type_);
+            }
+            for arg in args {
+                visitor.visit_expression_mut(arg);
+            }
+        }
+        ExpressionKind::Cast(x, type_) => {
+            visitor.visit_expression_mut(x);
+            visitor.visit_type_mut(type_);
+        }
+        ExpressionKind::Conditional(x, y, z) => {
+            visitor.visit_expression_mut(x);
+            visitor.visit_expression_mut(y);
+            visitor.visit_expression_mut(z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Ident, VariableType};
+
+    /// Names of every compound type used, in the order visited
+    #[derive(Default)]
+    struct TypeNames(Vec<String>);
+
+    impl Visitor for TypeNames {
+        fn visit_type(&mut self, type_: &TypeDeclaration) {
+            if let VariableType::Compound(name) = &type_.type_ {
+                self.0.push(name.as_str().to_string());
+            }
+            walk_type(self, type_);
+        }
+    }
+
+    struct Rename(&'static str, &'static str);
+
+    impl VisitorMut for Rename {
+        fn visit_type_mut(&mut self, type_: &mut TypeDeclaration) {
+            if let VariableType::Compound(name) = &mut type_.type_ {
+                if name.as_str() == self.0 {
+                    *name = Ident::new(self.1);
+                }
+            }
+            walk_type_mut(self, type_);
+        }
+    }
+
+    const SOURCE: &str = "
+public class Car extends Vehicle {
+    public let m_wheels: array<ref<Wheel>>;
+    public func Drive(driver: wref<Person>) -> Route;
+}
+func Park(car: Car);";
+
+    #[test]
+    fn visit_types() {
+        let file = crate::parse_file("test.reds", SOURCE).unwrap();
+        let mut names = TypeNames::default();
+        names.visit_file(&file);
+
+        assert_eq!(names.0, vec!["Wheel", "Person", "Route", "Car"]);
+    }
+
+    #[test]
+    fn visit_types_mut() {
+        let mut file = crate::parse_file("test.reds", SOURCE).unwrap();
+        Rename("Wheel", "Tyre").visit_file_mut(&mut file);
+        let mut names = TypeNames::default();
+        names.visit_file(&file);
+
+        assert_eq!(names.0, vec!["Tyre", "Person", "Route", "Car"]);
+    }
+
+    /// Names of every function or method called, in the order visited
+    #[derive(Default)]
+    struct Calls(Vec<String>);
+
+    impl Visitor for Calls {
+        fn visit_expression(&mut self, expr: &Expression) {
+            if let ExpressionKind::Call { callee, .. } = &expr.kind {
+                match &callee.kind {
+                    ExpressionKind::Ident(name) | ExpressionKind::Member(_, name) => self.0.push(name.to_string()),
+                    _ => {}
+                }
+            }
+            walk_expression(self, expr);
+        }
+    }
+
+    #[test]
+    fn visit_calls() {
+        let source = "func Park(car: Car) {
+    if car.IsMoving() {
+        car.Brake(Cast<Float>(GetSpeed(car)));
+    }
+}";
+        let mut file = crate::parse_file("test.reds", source).unwrap();
+        let mut calls = Calls::default();
+        calls.visit_file(&file);
+        assert!(calls.0.is_empty());
+
+        assert!(crate::parse_bodies(&mut file, source).is_empty());
+        calls.visit_file(&file);
+        assert_eq!(calls.0, vec!["IsMoving", "Brake", "Cast", "GetSpeed"]);
+    }
+}
@@ -0,0 +1,26 @@
+//! Memory management for hosts of the parser built for `wasm32-unknown-unknown`, which can only
+//! pass scripts in through the module's own memory. Scripts are parsed with the C API in
+//! [`crate::ffi`], and `js/redscript_parser.js` wraps the exports for JavaScript.
+
+#[cfg(feature = "trace")]
+compile_error!("the `trace` feature prints to stdout, which wasm32-unknown-unknown doesn't have");
+
+/// Allocates a buffer of `len` bytes for the host to write a script into, which must be released
+/// with `redscript_dealloc`
+#[no_mangle]
+pub extern "C" fn redscript_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Releases a buffer returned by `redscript_alloc`
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `redscript_alloc` for the same `len`, and not already released.
+#[no_mangle]
+pub unsafe extern "C" fn redscript_dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}